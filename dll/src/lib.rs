@@ -67,6 +67,11 @@ pub extern "C" fn MoveWindowToDesktopNumber(hwnd: HWND, desktop_number: i32) ->
     move_window_to_desktop(desktop_number as u32, &hwnd).map_or(-1, |_| 1)
 }
 
+#[no_mangle]
+pub extern "C" fn MoveWindowToDesktopNumberAndFollow(hwnd: HWND, desktop_number: i32) -> i32 {
+    move_window_and_follow(hwnd, desktop_number as u32).map_or(-1, |_| 1)
+}
+
 #[no_mangle]
 pub extern "C" fn GoToDesktopNumber(desktop_number: i32) -> i32 {
     switch_desktop(desktop_number as u32).map_or(-1, |_| 1)
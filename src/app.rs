@@ -0,0 +1,71 @@
+/// Applications identified by the application user model ID
+use crate::comobjects::with_com_objects;
+use crate::{Desktop, Result, Window};
+use std::fmt;
+
+/// Application, identified by its application user model ID (AUMID), get it
+/// with `Window::app()` or by the ID with `App::new()`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// // Move all Teams windows to the third desktop
+/// App::new("MSTeams_8wekyb3d8bbwe!MSTeams").move_all_to(2).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct App(String);
+
+impl App {
+    /// Application with the user model ID, e.g. `Microsoft.WindowsNotepad_8wekyb3d8bbwe!App`
+    pub fn new(id: &str) -> App {
+        App(id.to_owned())
+    }
+
+    /// Application user model ID
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+
+    /// Top level windows of the application shown in the task switcher
+    pub fn windows(&self) -> Result<Vec<Window>> {
+        let id = self.0.clone();
+        with_com_objects(move |o| o.get_app_windows(&id))
+            .map(|windows| windows.into_iter().map(Window::from).collect())
+    }
+
+    /// Move all windows of the application shown in the task switcher to
+    /// desktop by index or GUID, returns the number of windows moved
+    pub fn move_all_to<T>(&self, desktop: T) -> Result<u32>
+    where
+        T: Into<Desktop>,
+        T: Send + 'static + Copy,
+    {
+        let id = self.0.clone();
+        with_com_objects(move |o| o.move_app_to_desktop(&id, &desktop.into().into()))
+    }
+
+    /// Is the application pinned to all desktops?
+    pub fn is_pinned(&self) -> Result<bool> {
+        let id = self.0.clone();
+        with_com_objects(move |o| o.is_pinned_app_id(&id))
+    }
+
+    /// Pin the application to all desktops
+    pub fn pin(&self) -> Result<()> {
+        let id = self.0.clone();
+        with_com_objects(move |o| o.pin_app_id(&id))
+    }
+
+    /// Unpin the application
+    pub fn unpin(&self) -> Result<()> {
+        let id = self.0.clone();
+        with_com_objects(move |o| o.unpin_app_id(&id))
+    }
+}
+
+impl fmt::Display for App {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,406 @@
+/// Desktop operations used by the multi-step functions, e.g. swapping and
+/// merging desktops
+///
+/// The multi-step functions are written against `DesktopBackend` so that
+/// they can be tested with the simulated backend instead of explorer.exe.
+use crate::comobjects::{ComObjects, DesktopInternal};
+use crate::{Desktop, DesktopSnapshot, DesktopState, Error, Result};
+use windows::core::GUID;
+use windows::Win32::Foundation::HWND;
+
+pub(crate) trait DesktopBackend {
+    /// Desktop IDs in the index order
+    fn desktop_ids(&self) -> Result<Vec<GUID>>;
+    fn current_desktop(&self) -> Result<GUID>;
+
+    /// Desktops of the windows in the z-order, the most recently used first
+    fn recent_desktops(&self) -> Result<Vec<GUID>>;
+    fn switch_desktop(&self, id: GUID) -> Result<()>;
+
+    /// Create a desktop as the last one
+    fn create_desktop(&self) -> Result<GUID>;
+
+    /// Remove the desktop, its windows are moved to the fallback desktop
+    fn remove_desktop(&self, id: GUID, fallback: GUID) -> Result<()>;
+    fn move_desktop(&self, id: GUID, index: u32) -> Result<()>;
+    fn desktop_name(&self, id: GUID) -> Result<String>;
+    fn set_desktop_name(&self, id: GUID, name: &str) -> Result<()>;
+    fn desktop_wallpaper(&self, id: GUID) -> Result<String>;
+    fn set_desktop_wallpaper(&self, id: GUID, path: &str) -> Result<()>;
+
+    /// Windows on the desktop in the z-order
+    fn desktop_windows(&self, id: GUID) -> Result<Vec<HWND>>;
+    fn is_window_pinned(&self, hwnd: HWND) -> Result<bool>;
+    fn pin_window(&self, hwnd: HWND) -> Result<()>;
+    fn pin_app(&self, app_id: &str) -> Result<()>;
+    fn move_window(&self, hwnd: HWND, id: GUID) -> Result<()>;
+
+    /// ID of the desktop given by index or GUID
+    fn resolve(&self, desktop: &Desktop) -> Result<GUID> {
+        let ids = self.desktop_ids()?;
+        let id = match DesktopInternal::from(*desktop) {
+            DesktopInternal::Index(index) => ids.get(index as usize).copied(),
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => {
+                ids.iter().find(|d| **d == id).copied()
+            }
+        };
+        id.ok_or(Error::DesktopNotFound)
+    }
+
+    /// Names and wallpapers of the desktops, and the current desktop
+    fn snapshot(&self) -> Result<DesktopSnapshot> {
+        let desktops = self
+            .desktop_ids()?
+            .into_iter()
+            .map(|id| {
+                Ok(DesktopState {
+                    id,
+                    name: self.desktop_name(id)?,
+                    wallpaper: self.desktop_wallpaper(id)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DesktopSnapshot {
+            desktops,
+            current: Some(self.current_desktop()?),
+        })
+    }
+
+    /// Index of the desktop
+    fn index_of(&self, id: GUID) -> Result<u32> {
+        self.desktop_ids()?
+            .iter()
+            .position(|d| *d == id)
+            .map(|i| i as u32)
+            .ok_or(Error::DesktopNotFound)
+    }
+}
+
+impl DesktopBackend for ComObjects {
+    fn desktop_ids(&self) -> Result<Vec<GUID>> {
+        self.get_desktops()?
+            .iter()
+            .map(|d| self.get_desktop_id(d))
+            .collect()
+    }
+
+    fn snapshot(&self) -> Result<DesktopSnapshot> {
+        self.get_snapshot()
+    }
+
+    fn current_desktop(&self) -> Result<GUID> {
+        self.get_desktop_id(&self.get_current_desktop()?)
+    }
+
+    fn recent_desktops(&self) -> Result<Vec<GUID>> {
+        self.get_recent_desktops()
+    }
+
+    fn switch_desktop(&self, id: GUID) -> Result<()> {
+        ComObjects::switch_desktop(self, &DesktopInternal::Guid(id))
+    }
+
+    fn create_desktop(&self) -> Result<GUID> {
+        self.get_desktop_id(&ComObjects::create_desktop(self)?)
+    }
+
+    fn remove_desktop(&self, id: GUID, fallback: GUID) -> Result<()> {
+        ComObjects::remove_desktop(
+            self,
+            &DesktopInternal::Guid(id),
+            &DesktopInternal::Guid(fallback),
+        )
+    }
+
+    fn move_desktop(&self, id: GUID, index: u32) -> Result<()> {
+        ComObjects::move_desktop(self, &DesktopInternal::Guid(id), index)
+    }
+
+    fn desktop_name(&self, id: GUID) -> Result<String> {
+        self.get_desktop_name(&DesktopInternal::Guid(id))
+    }
+
+    fn set_desktop_name(&self, id: GUID, name: &str) -> Result<()> {
+        ComObjects::set_desktop_name(self, &DesktopInternal::Guid(id), name)
+    }
+
+    fn desktop_wallpaper(&self, id: GUID) -> Result<String> {
+        self.get_desktop_wallpaper(&DesktopInternal::Guid(id))
+    }
+
+    fn set_desktop_wallpaper(&self, id: GUID, path: &str) -> Result<()> {
+        ComObjects::set_desktop_wallpaper(self, &DesktopInternal::Guid(id), path)
+    }
+
+    fn desktop_windows(&self, id: GUID) -> Result<Vec<HWND>> {
+        self.get_desktop_windows(&DesktopInternal::Guid(id))
+    }
+
+    fn is_window_pinned(&self, hwnd: HWND) -> Result<bool> {
+        self.is_pinned_window(&hwnd)
+    }
+
+    fn pin_window(&self, hwnd: HWND) -> Result<()> {
+        ComObjects::pin_window(self, &hwnd)
+    }
+
+    fn pin_app(&self, app_id: &str) -> Result<()> {
+        self.pin_app_id(app_id)
+    }
+
+    fn move_window(&self, hwnd: HWND, id: GUID) -> Result<()> {
+        self.move_window_to_desktop(&hwnd, &DesktopInternal::Guid(id))
+    }
+}
+
+/// In-memory backend for testing
+#[cfg(test)]
+pub(crate) mod simulated {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) struct SimDesktop {
+        pub id: GUID,
+        pub name: String,
+        pub wallpaper: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) struct SimWindow {
+        pub hwnd: isize,
+        pub desktop: GUID,
+        pub pinned: bool,
+    }
+
+    #[derive(Debug, Default)]
+    pub(crate) struct SimulatedBackend {
+        pub desktops: RefCell<Vec<SimDesktop>>,
+
+        /// Windows in the z-order
+        pub windows: RefCell<Vec<SimWindow>>,
+        pub current: RefCell<GUID>,
+
+        /// Failing operations, e.g. `move_window` fails all window moves and
+        /// `move_window:3` fails moving the window 3
+        pub failing: RefCell<HashSet<String>>,
+        pub pinned_apps: RefCell<Vec<String>>,
+        next_id: RefCell<u32>,
+    }
+
+    pub(crate) fn guid(n: u32) -> GUID {
+        GUID::from_values(n, 0, 0, [0; 8])
+    }
+
+    impl SimulatedBackend {
+        /// Desktops with IDs `guid(1)..=guid(count)` named "D1", "D2"...,
+        /// the first one is current
+        pub fn new(count: u32) -> SimulatedBackend {
+            let backend = SimulatedBackend::default();
+            for n in 1..=count {
+                backend.desktops.borrow_mut().push(SimDesktop {
+                    id: guid(n),
+                    name: format!("D{}", n),
+                    wallpaper: format!("C:\\wall{}.jpg", n),
+                });
+            }
+            *backend.current.borrow_mut() = guid(1);
+            *backend.next_id.borrow_mut() = count + 1;
+            backend
+        }
+
+        /// Add a window below the other windows in the z-order
+        pub fn with_window(self, hwnd: isize, desktop: u32, pinned: bool) -> Self {
+            self.windows.borrow_mut().push(SimWindow {
+                hwnd,
+                desktop: guid(desktop),
+                pinned,
+            });
+            self
+        }
+
+        pub fn fail(&self, operation: &str) {
+            self.failing.borrow_mut().insert(operation.to_owned());
+        }
+
+        pub fn names(&self) -> Vec<String> {
+            self.desktops
+                .borrow()
+                .iter()
+                .map(|d| d.name.clone())
+                .collect()
+        }
+
+        pub fn windows_on(&self, desktop: u32) -> Vec<isize> {
+            self.windows
+                .borrow()
+                .iter()
+                .filter(|w| w.desktop == guid(desktop))
+                .map(|w| w.hwnd)
+                .collect()
+        }
+
+        fn check(&self, operation: &str, target: Option<isize>) -> Result<()> {
+            let failing = self.failing.borrow();
+            let specific = target.map(|t| format!("{}:{}", operation, t));
+            if failing.contains(operation) || specific.is_some_and(|s| failing.contains(&s)) {
+                return Err(Error::ServerBusy);
+            }
+            Ok(())
+        }
+
+        fn with_desktop<R>(&self, id: GUID, f: impl FnOnce(&mut SimDesktop) -> R) -> Result<R> {
+            let mut desktops = self.desktops.borrow_mut();
+            let desktop = desktops
+                .iter_mut()
+                .find(|d| d.id == id)
+                .ok_or(Error::DesktopNotFound)?;
+            Ok(f(desktop))
+        }
+    }
+
+    impl DesktopBackend for SimulatedBackend {
+        fn desktop_ids(&self) -> Result<Vec<GUID>> {
+            self.check("desktop_ids", None)?;
+            Ok(self.desktops.borrow().iter().map(|d| d.id).collect())
+        }
+
+        fn current_desktop(&self) -> Result<GUID> {
+            self.check("current_desktop", None)?;
+            Ok(*self.current.borrow())
+        }
+
+        fn recent_desktops(&self) -> Result<Vec<GUID>> {
+            self.check("recent_desktops", None)?;
+            let mut desktops = Vec::new();
+            for window in self.windows.borrow().iter() {
+                if !desktops.contains(&window.desktop) {
+                    desktops.push(window.desktop);
+                }
+            }
+            Ok(desktops)
+        }
+
+        fn switch_desktop(&self, id: GUID) -> Result<()> {
+            self.check("switch_desktop", None)?;
+            self.index_of(id)?;
+            *self.current.borrow_mut() = id;
+            Ok(())
+        }
+
+        fn create_desktop(&self) -> Result<GUID> {
+            self.check("create_desktop", None)?;
+            let id = guid(*self.next_id.borrow());
+            *self.next_id.borrow_mut() += 1;
+            self.desktops.borrow_mut().push(SimDesktop {
+                id,
+                name: String::new(),
+                wallpaper: String::new(),
+            });
+            Ok(id)
+        }
+
+        fn remove_desktop(&self, id: GUID, fallback: GUID) -> Result<()> {
+            self.check("remove_desktop", None)?;
+            let index = self.index_of(id)?;
+            self.index_of(fallback)?;
+            if id == fallback {
+                return Err(Error::RemoveDesktopFailed);
+            }
+            self.desktops.borrow_mut().remove(index as usize);
+            for window in self.windows.borrow_mut().iter_mut() {
+                if window.desktop == id {
+                    window.desktop = fallback;
+                }
+            }
+            if *self.current.borrow() == id {
+                *self.current.borrow_mut() = fallback;
+            }
+            Ok(())
+        }
+
+        fn move_desktop(&self, id: GUID, index: u32) -> Result<()> {
+            self.check("move_desktop", None)?;
+            let from = self.index_of(id)? as usize;
+            let mut desktops = self.desktops.borrow_mut();
+            if index as usize >= desktops.len() {
+                return Err(Error::InvalidArgument);
+            }
+            let desktop = desktops.remove(from);
+            desktops.insert(index as usize, desktop);
+            Ok(())
+        }
+
+        fn desktop_name(&self, id: GUID) -> Result<String> {
+            self.check("desktop_name", None)?;
+            self.with_desktop(id, |d| d.name.clone())
+        }
+
+        fn set_desktop_name(&self, id: GUID, name: &str) -> Result<()> {
+            self.check("set_desktop_name", None)?;
+            self.with_desktop(id, |d| d.name = name.to_owned())
+        }
+
+        fn desktop_wallpaper(&self, id: GUID) -> Result<String> {
+            self.check("desktop_wallpaper", None)?;
+            self.with_desktop(id, |d| d.wallpaper.clone())
+        }
+
+        fn set_desktop_wallpaper(&self, id: GUID, path: &str) -> Result<()> {
+            self.check("set_desktop_wallpaper", None)?;
+            self.with_desktop(id, |d| d.wallpaper = path.to_owned())
+        }
+
+        fn desktop_windows(&self, id: GUID) -> Result<Vec<HWND>> {
+            self.check("desktop_windows", None)?;
+            self.index_of(id)?;
+            Ok(self
+                .windows
+                .borrow()
+                .iter()
+                .filter(|w| w.desktop == id)
+                .map(|w| HWND(w.hwnd))
+                .collect())
+        }
+
+        fn is_window_pinned(&self, hwnd: HWND) -> Result<bool> {
+            self.check("is_window_pinned", Some(hwnd.0))?;
+            self.windows
+                .borrow()
+                .iter()
+                .find(|w| w.hwnd == hwnd.0)
+                .map(|w| w.pinned)
+                .ok_or(Error::WindowNotFound)
+        }
+
+        fn pin_window(&self, hwnd: HWND) -> Result<()> {
+            self.check("pin_window", Some(hwnd.0))?;
+            let mut windows = self.windows.borrow_mut();
+            let window = windows
+                .iter_mut()
+                .find(|w| w.hwnd == hwnd.0)
+                .ok_or(Error::WindowNotFound)?;
+            window.pinned = true;
+            Ok(())
+        }
+
+        fn pin_app(&self, app_id: &str) -> Result<()> {
+            self.check("pin_app", None)?;
+            self.pinned_apps.borrow_mut().push(app_id.to_owned());
+            Ok(())
+        }
+
+        fn move_window(&self, hwnd: HWND, id: GUID) -> Result<()> {
+            self.check("move_window", Some(hwnd.0))?;
+            self.index_of(id)?;
+            let mut windows = self.windows.borrow_mut();
+            let window = windows
+                .iter_mut()
+                .find(|w| w.hwnd == hwnd.0)
+                .ok_or(Error::WindowNotFound)?;
+            window.desktop = id;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,171 @@
+/// Creating a configured desktop in one call
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::remove::remove_desktop_with;
+use crate::{Desktop, Error, FallbackStrategy, Result};
+use windows::core::GUID;
+
+/// Builder for a new desktop, get it with `create_desktop_with()`
+///
+/// All steps are done in one call to the COM objects. If any of the steps
+/// fails the new desktop is removed, and the error of the step is returned.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let desktop = create_desktop_with()
+///     .name("Build")
+///     .wallpaper("C:\\wallpapers\\build.jpg")
+///     .at_index(0)
+///     .switch_to(true)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DesktopBuilder {
+    name: Option<String>,
+    wallpaper: Option<String>,
+    index: Option<u32>,
+    switch_to: bool,
+}
+
+/// Create a desktop with the name, wallpaper and position given to the
+/// builder
+pub fn create_desktop_with() -> DesktopBuilder {
+    DesktopBuilder::default()
+}
+
+impl DesktopBuilder {
+    /// Name of the desktop
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Wallpaper path of the desktop
+    pub fn wallpaper(mut self, path: &str) -> Self {
+        self.wallpaper = Some(path.to_owned());
+        self
+    }
+
+    /// Position of the desktop, by default it is created as the last one
+    pub fn at_index(mut self, index: u32) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Switch to the desktop after it is created
+    pub fn switch_to(mut self, switch_to: bool) -> Self {
+        self.switch_to = switch_to;
+        self
+    }
+
+    /// Create the desktop
+    pub fn build(self) -> Result<Desktop> {
+        with_com_objects(move |o| self.build_with(o))
+    }
+
+    pub(crate) fn build_with<B: DesktopBackend + ?Sized>(&self, backend: &B) -> Result<Desktop> {
+        // The new desktop can be placed at most after the last one
+        if let Some(index) = self.index {
+            if index as usize > backend.desktop_ids()?.len() {
+                return Err(Error::InvalidArgument);
+            }
+        }
+        let id = backend.create_desktop()?;
+        match self.configure(backend, id) {
+            Ok(()) => Ok(id.into()),
+            Err(er) => {
+                if let Err(rollback) =
+                    remove_desktop_with(backend, &id.into(), FallbackStrategy::MostRecentlyUsed)
+                {
+                    ::log::warn!(
+                        target: crate::log::COM,
+                        "removing desktop {:?} after failed creation failed: {}",
+                        id,
+                        rollback
+                    );
+                }
+                Err(er)
+            }
+        }
+    }
+
+    fn configure<B: DesktopBackend + ?Sized>(&self, backend: &B, id: GUID) -> Result<()> {
+        if let Some(name) = &self.name {
+            backend.set_desktop_name(id, name)?;
+        }
+        if let Some(wallpaper) = &self.wallpaper {
+            backend.set_desktop_wallpaper(id, wallpaper)?;
+        }
+        if let Some(index) = self.index {
+            backend.move_desktop(id, index)?;
+        }
+        if self.switch_to {
+            backend.switch_desktop(id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+
+    #[test]
+    fn test_build() {
+        let backend = SimulatedBackend::new(3);
+        let desktop = create_desktop_with()
+            .name("Build")
+            .wallpaper("C:\\build.jpg")
+            .at_index(1)
+            .switch_to(true)
+            .build_with(&backend)
+            .unwrap();
+        assert_eq!(desktop, Desktop::from(guid(4)));
+        assert_eq!(backend.names(), vec!["D1", "Build", "D2", "D3"]);
+        assert_eq!(backend.desktop_wallpaper(guid(4)).unwrap(), "C:\\build.jpg");
+        assert_eq!(*backend.current.borrow(), guid(4));
+    }
+
+    #[test]
+    fn test_build_defaults() {
+        let backend = SimulatedBackend::new(2);
+        let desktop = create_desktop_with().build_with(&backend).unwrap();
+        assert_eq!(desktop, Desktop::from(guid(3)));
+        assert_eq!(backend.names(), vec!["D1", "D2", ""]);
+        assert_eq!(*backend.current.borrow(), guid(1));
+
+        let desktop = create_desktop_with()
+            .at_index(3)
+            .build_with(&backend)
+            .unwrap();
+        assert_eq!(backend.index_of(guid(4)).unwrap(), 3);
+        assert_eq!(desktop, Desktop::from(guid(4)));
+    }
+
+    #[test]
+    fn test_build_rolls_back() {
+        let backend = SimulatedBackend::new(2);
+        backend.fail("set_desktop_wallpaper");
+        let result = create_desktop_with()
+            .name("Build")
+            .wallpaper("C:\\build.jpg")
+            .build_with(&backend);
+        assert_eq!(result, Err(Error::ServerBusy));
+        assert_eq!(backend.names(), vec!["D1", "D2"]);
+        assert_eq!(*backend.current.borrow(), guid(1));
+    }
+
+    #[test]
+    fn test_build_invalid_index() {
+        let backend = SimulatedBackend::new(2);
+        assert_eq!(
+            create_desktop_with().at_index(3).build_with(&backend),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(backend.names(), vec!["D1", "D2"]);
+    }
+}
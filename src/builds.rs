@@ -0,0 +1,268 @@
+/// Windows build detection, and the interface profiles for each build
+///
+/// The undocumented virtual desktop interfaces change between Windows builds,
+/// either by getting new IIDs or by having methods added in the middle of the
+/// vtable. Each profile tells which IIDs and which vtable layout to use for a
+/// range of builds and their update revisions.
+use crate::{Error, Result};
+use std::fmt;
+use std::sync::OnceLock;
+use windows::core::{w, GUID};
+use windows::Wdk::System::SystemServices::RtlGetVersion;
+use windows::Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_DWORD};
+use windows::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+/// Windows build and its update revision (UBR), e.g. 22631.3085
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WindowsVersion {
+    pub build: u32,
+    pub revision: u32,
+}
+
+impl WindowsVersion {
+    pub fn new(build: u32, revision: u32) -> WindowsVersion {
+        WindowsVersion { build, revision }
+    }
+}
+
+impl fmt::Display for WindowsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.build, self.revision)
+    }
+}
+
+/// Method layout of the interfaces
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceLayout {
+    /// Windows 11 22H2 and 23H2, and 24H2 before 26100.2605
+    Win11_23H2,
+
+    /// Windows 11 24H2 from 26100.2605, `IVirtualDesktopManagerInternal` has
+    /// `switch_desktop_and_move_foreground_view` after `switch_desktop`
+    Win11_24H2,
+}
+
+/// Interface IDs used for a build
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceIds {
+    pub virtual_desktop: GUID,
+    pub manager_internal: GUID,
+    pub notification: GUID,
+    pub notification_service: GUID,
+}
+
+/// Interfaces for a range of Windows builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildProfile {
+    pub name: &'static str,
+
+    /// First build of the profile
+    pub min_build: u32,
+
+    /// Last build of the profile, `None` means all later builds
+    pub max_build: Option<u32>,
+
+    /// First update revision of the builds, 22621 and 22631 get the same
+    /// updates so they share the revision numbers
+    pub min_revision: u32,
+
+    pub iids: InterfaceIds,
+    pub layout: InterfaceLayout,
+}
+
+/// Windows 11 22H2 and 23H2 from 22621.2215 until 22631.3085
+const IIDS_WIN11_22H2: InterfaceIds = InterfaceIds {
+    virtual_desktop: GUID::from_u128(0x3F07F4BE_B107_441A_AF0F_39D82529072C),
+    manager_internal: GUID::from_u128(0xA3175F2D_239C_4BD2_8AA0_EEBA8B0B138E),
+    notification: GUID::from_u128(0xB287FA1C_7771_471A_A2DF_9B6B21F0D675),
+    notification_service: GUID::from_u128(0x0CD45E71_D927_4F15_8B0A_8FEF525337BF),
+};
+
+/// Windows 11 23H2 from 22631.3085, 24H2 kept the IIDs but changed the
+/// vtable of `IVirtualDesktopManagerInternal`
+const IIDS_WIN11_23H2: InterfaceIds = InterfaceIds {
+    virtual_desktop: GUID::from_u128(0x3F07F4BE_B107_441A_AF0F_39D82529072C),
+    manager_internal: GUID::from_u128(0x53F5CA0B_158F_4124_900C_057158060B27),
+    notification: GUID::from_u128(0xB9E5E94D_233E_49AB_AF5C_2B4541C3AADE),
+    notification_service: GUID::from_u128(0x0CD45E71_D927_4F15_8B0A_8FEF525337BF),
+};
+
+/// Known builds, in the build and revision order
+pub const BUILD_PROFILES: &[BuildProfile] = &[
+    BuildProfile {
+        name: "Windows 11 22H2",
+        min_build: 22621,
+        max_build: Some(26099),
+        min_revision: 2215,
+        iids: IIDS_WIN11_22H2,
+        layout: InterfaceLayout::Win11_23H2,
+    },
+    BuildProfile {
+        name: "Windows 11 23H2",
+        min_build: 22621,
+        max_build: Some(26099),
+        min_revision: 3085,
+        iids: IIDS_WIN11_23H2,
+        layout: InterfaceLayout::Win11_23H2,
+    },
+    BuildProfile {
+        name: "Windows 11 24H2 before 2605",
+        min_build: 26100,
+        max_build: None,
+        min_revision: 0,
+        iids: IIDS_WIN11_23H2,
+        layout: InterfaceLayout::Win11_23H2,
+    },
+    BuildProfile {
+        name: "Windows 11 24H2",
+        min_build: 26100,
+        max_build: None,
+        min_revision: 2605,
+        iids: IIDS_WIN11_23H2,
+        layout: InterfaceLayout::Win11_24H2,
+    },
+];
+
+/// Select the profile for the Windows version, the last profile with the
+/// build in range and the revision at least `min_revision` is selected
+pub fn select_build_profile(version: WindowsVersion) -> Option<&'static BuildProfile> {
+    BUILD_PROFILES.iter().rev().find(|p| {
+        version.build >= p.min_build
+            && !matches!(p.max_build, Some(max) if version.build > max)
+            && version.revision >= p.min_revision
+    })
+}
+
+/// Windows build number, e.g. 22631
+pub fn get_windows_build() -> Result<u32> {
+    static BUILD: OnceLock<u32> = OnceLock::new();
+    if let Some(build) = BUILD.get() {
+        return Ok(*build);
+    }
+    let mut info = OSVERSIONINFOW {
+        dwOSVersionInfoSize: std::mem::size_of::<OSVERSIONINFOW>() as u32,
+        ..Default::default()
+    };
+    unsafe { RtlGetVersion(&mut info) }.ok()?;
+    Ok(*BUILD.get_or_init(|| info.dwBuildNumber))
+}
+
+/// Windows build and the update revision, e.g. 22631.3085
+///
+/// The revision is read from the `UBR` value of the registry, as
+/// `RtlGetVersion` does not give it.
+pub fn get_windows_version() -> Result<WindowsVersion> {
+    static REVISION: OnceLock<u32> = OnceLock::new();
+    let build = get_windows_build()?;
+    if let Some(revision) = REVISION.get() {
+        return Ok(WindowsVersion::new(build, *revision));
+    }
+    let mut revision = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion"),
+            w!("UBR"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut revision as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+    Ok(WindowsVersion::new(
+        build,
+        *REVISION.get_or_init(|| revision),
+    ))
+}
+
+/// Profile for the running Windows build
+pub fn get_build_profile() -> Result<&'static BuildProfile> {
+    let version = get_windows_version()?;
+    select_build_profile(version).ok_or(Error::UnsupportedWindowsBuild(version.build))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(build: u32, revision: u32) -> Option<&'static str> {
+        select_build_profile(WindowsVersion::new(build, revision)).map(|p| p.name)
+    }
+
+    #[test]
+    fn test_select_build_profile() {
+        assert_eq!(name(19045, 4000), None);
+        assert_eq!(name(22000, 3085), None);
+        assert_eq!(name(22621, 2215), Some("Windows 11 22H2"));
+        assert_eq!(name(22621, 3085), Some("Windows 11 23H2"));
+        assert_eq!(name(26100, 1742), Some("Windows 11 24H2 before 2605"));
+        assert_eq!(name(27000, 2605), Some("Windows 11 24H2"));
+        assert_eq!(
+            select_build_profile(WindowsVersion::new(26100, 2605)).map(|p| p.layout),
+            Some(InterfaceLayout::Win11_24H2)
+        );
+    }
+
+    #[test]
+    fn test_select_build_profile_revision_boundary() {
+        let old = select_build_profile(WindowsVersion::new(22631, 3084)).unwrap();
+        let new = select_build_profile(WindowsVersion::new(22631, 3085)).unwrap();
+        assert_eq!(old.iids, IIDS_WIN11_22H2);
+        assert_eq!(new.iids, IIDS_WIN11_23H2);
+        assert_ne!(old.iids.manager_internal, new.iids.manager_internal);
+        assert_eq!(old.layout, new.layout);
+
+        // Revisions before the known IIDs are not supported
+        assert_eq!(name(22631, 2214), None);
+    }
+
+    #[test]
+    fn test_select_build_profile_24h2_layout_boundary() {
+        let old = select_build_profile(WindowsVersion::new(26100, 2604)).unwrap();
+        let new = select_build_profile(WindowsVersion::new(26100, 2605)).unwrap();
+        assert_eq!(old.layout, InterfaceLayout::Win11_23H2);
+        assert_eq!(new.layout, InterfaceLayout::Win11_24H2);
+        assert_eq!(old.iids, new.iids);
+    }
+
+    #[test]
+    fn test_build_profiles_are_ordered() {
+        for pair in BUILD_PROFILES.windows(2) {
+            if pair[0].min_build == pair[1].min_build {
+                // Same builds, later revisions
+                assert_eq!(pair[0].max_build, pair[1].max_build);
+                assert!(pair[0].min_revision < pair[1].min_revision);
+            } else {
+                let max = pair[0].max_build.expect("Only the last builds are open");
+                assert!(pair[0].min_build <= max);
+                assert_eq!(max + 1, pair[1].min_build);
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_profile_iids_match_interfaces() {
+        use crate::interfaces::*;
+        use windows::core::Interface;
+        let iids = &select_build_profile(WindowsVersion::new(22631, 3155))
+            .unwrap()
+            .iids;
+        assert_eq!(iids.virtual_desktop, IVirtualDesktop::IID);
+        assert_eq!(iids.manager_internal, IVirtualDesktopManagerInternal::IID);
+        assert_eq!(iids.notification, IVirtualDesktopNotification::IID);
+        assert_eq!(
+            iids.notification_service,
+            IVirtualDesktopNotificationService::IID
+        );
+    }
+
+    #[test]
+    fn test_windows_version_order() {
+        assert!(WindowsVersion::new(22631, 3084) < WindowsVersion::new(22631, 3085));
+        assert!(WindowsVersion::new(22621, 9999) < WindowsVersion::new(22631, 0));
+        assert_eq!(WindowsVersion::new(22631, 3085).to_string(), "22631.3085");
+    }
+}
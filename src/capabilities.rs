@@ -0,0 +1,211 @@
+/// Probing which virtual desktop features work on the running Windows build
+use crate::builds::{get_windows_version, select_build_profile, BuildProfile, WindowsVersion};
+use crate::comobjects::with_com_objects;
+use crate::interfaces::IVirtualDesktopNotification;
+use crate::Result;
+use windows::core::Interface;
+
+/// Result of resolving each COM service, the services except the provider
+/// are resolved through `IServiceProvider::query_service`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceStatus {
+    pub provider: Result<()>,
+    pub manager: Result<()>,
+    pub manager_internal: Result<()>,
+    pub notification_service: Result<()>,
+    pub pinned_apps: Result<()>,
+    pub view_collection: Result<()>,
+}
+
+impl ServiceStatus {
+    /// Are all services resolved?
+    pub fn all_connected(&self) -> bool {
+        self.iter().all(|(_, status)| status.is_ok())
+    }
+
+    /// Service names and their statuses
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Result<()>)> {
+        [
+            ("provider", &self.provider),
+            ("manager", &self.manager),
+            ("manager_internal", &self.manager_internal),
+            ("notification_service", &self.notification_service),
+            ("pinned_apps", &self.pinned_apps),
+            ("view_collection", &self.view_collection),
+        ]
+        .into_iter()
+    }
+}
+
+/// Features available on the running Windows build
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    /// Windows build number, e.g. 22631
+    pub build: u32,
+
+    /// Update revision of the build, e.g. 3085
+    pub revision: u32,
+
+    /// Interface profile for the build, `None` if the build is not supported
+    pub profile: Option<&'static BuildProfile>,
+
+    pub services: ServiceStatus,
+
+    /// Switching, creating and removing desktops
+    pub desktops: bool,
+
+    /// Getting and setting desktop names
+    pub desktop_names: bool,
+
+    /// Getting and setting desktop wallpapers
+    pub desktop_wallpapers: bool,
+
+    /// Pinning windows and apps
+    pub pinned_apps: bool,
+
+    /// Moving windows between desktops
+    pub move_windows: bool,
+
+    /// Moving desktops to other index, this is not probed but inferred from
+    /// `desktops`, as probing would have to move a desktop
+    pub move_desktop: bool,
+
+    /// Listening desktop events, if this is false use
+    /// `listen_desktop_events_polling`
+    pub notifications: bool,
+}
+
+impl Capabilities {
+    /// Decide the capabilities from the version, the resolved services, and
+    /// the results of reading the name and wallpaper of the current desktop
+    pub(crate) fn from_probes(
+        version: WindowsVersion,
+        services: ServiceStatus,
+        name_probe: Result<()>,
+        wallpaper_probe: Result<()>,
+    ) -> Capabilities {
+        let profile = select_build_profile(version);
+        let desktops = profile.is_some() && services.manager_internal.is_ok();
+        // The listener implements the compiled notification interface only
+        let notification_iid = profile.map(|p| p.iids.notification);
+        Capabilities {
+            build: version.build,
+            revision: version.revision,
+            profile,
+            desktops,
+            desktop_names: desktops && name_probe.is_ok(),
+            desktop_wallpapers: desktops && wallpaper_probe.is_ok(),
+            pinned_apps: services.pinned_apps.is_ok() && services.view_collection.is_ok(),
+            move_windows: desktops && services.view_collection.is_ok(),
+            // `move_desktop` is in the vtable of all known layouts
+            move_desktop: desktops,
+            notifications: desktops
+                && services.notification_service.is_ok()
+                && notification_iid == Some(IVirtualDesktopNotification::IID),
+            services,
+        }
+    }
+}
+
+/// Probe which features work, this connects to each COM service and reads
+/// the name and wallpaper of the current desktop
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let capabilities = capabilities().unwrap();
+/// if !capabilities.desktop_names {
+///     println!("Desktop names are not supported on build {}", capabilities.build);
+/// }
+/// ```
+pub fn capabilities() -> Result<Capabilities> {
+    let version = get_windows_version()?;
+    with_com_objects(move |o| {
+        let services = o.probe_services();
+        let current = o.get_current_desktop();
+        let name_probe = current
+            .clone()
+            .and_then(|desktop| o.get_desktop_name(&desktop).map(|_| ()));
+        let wallpaper_probe =
+            current.and_then(|desktop| o.get_desktop_wallpaper(&desktop).map(|_| ()));
+        Ok(Capabilities::from_probes(
+            version,
+            services,
+            name_probe,
+            wallpaper_probe,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn connected() -> ServiceStatus {
+        ServiceStatus {
+            provider: Ok(()),
+            manager: Ok(()),
+            manager_internal: Ok(()),
+            notification_service: Ok(()),
+            pinned_apps: Ok(()),
+            view_collection: Ok(()),
+        }
+    }
+
+    #[test]
+    fn test_all_capabilities() {
+        let c = Capabilities::from_probes(
+            WindowsVersion::new(22631, 3155),
+            connected(),
+            Ok(()),
+            Ok(()),
+        );
+        assert!(c.services.all_connected());
+        assert!(c.desktops && c.desktop_names && c.desktop_wallpapers);
+        assert!(c.pinned_apps && c.move_windows && c.move_desktop && c.notifications);
+    }
+
+    #[test]
+    fn test_unsupported_build() {
+        let c = Capabilities::from_probes(
+            WindowsVersion::new(19045, 4529),
+            connected(),
+            Ok(()),
+            Ok(()),
+        );
+        assert_eq!(c.profile, None);
+        assert!(!c.desktops && !c.desktop_names && !c.move_desktop && !c.notifications);
+    }
+
+    #[test]
+    fn test_notifications_need_compiled_interface() {
+        let c = Capabilities::from_probes(
+            WindowsVersion::new(22631, 3084),
+            connected(),
+            Ok(()),
+            Ok(()),
+        );
+        assert_eq!(c.profile.map(|p| p.name), Some("Windows 11 22H2"));
+        assert!(c.desktops && !c.notifications);
+    }
+
+    #[test]
+    fn test_missing_services() {
+        let services = ServiceStatus {
+            notification_service: Err(Error::NoInterface),
+            pinned_apps: Err(Error::ComError(windows::core::HRESULT(-1))),
+            ..connected()
+        };
+        let c = Capabilities::from_probes(
+            WindowsVersion::new(22631, 3155),
+            services,
+            Ok(()),
+            Err(Error::NotImplemented),
+        );
+        assert!(!c.services.all_connected());
+        assert!(c.desktops && c.desktop_names && c.move_windows);
+        assert!(!c.desktop_wallpapers && !c.pinned_apps && !c.notifications);
+    }
+}
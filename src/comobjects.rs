@@ -1,65 +1,31 @@
 /// This module contains COM object for accessing the Windows Virtual Desktop API
 use super::interfaces::*;
 use super::Result;
+use crate::builds::{get_build_profile, get_windows_build, BuildProfile, InterfaceLayout};
+pub use crate::error::Error;
+use crate::error::{
+    check_desktop_index, desktop_lookup_error, error_from_hresult, window_lookup_error,
+};
+use crate::retry::{get_retry_policy, run_with_retry, with_retry_policy, RetryBackend};
+use crate::worker::{call_in_worker, should_use_worker};
+use crate::{Desktop, DesktopSnapshot, DesktopState, ServiceStatus};
 use std::convert::TryFrom;
 use std::rc::Rc;
 use std::{cell::RefCell, ffi::c_void};
-use windows::core::HRESULT;
 use windows::Win32::Foundation::HWND;
 use windows::Win32::System::Com::CoIncrementMTAUsage;
 use windows::Win32::System::Com::CLSCTX_LOCAL_SERVER;
 use windows::{
-    core::{Interface, GUID, HSTRING},
+    core::{IUnknown, Interface, GUID, HSTRING, PCWSTR},
     Win32::{System::Com::CoCreateInstance, UI::Shell::Common::IObjectArray},
 };
 
-#[cfg(debug_assertions)]
-use crate::log::log_output;
+use crate::log::COM;
+use crate::metrics::record_call;
 
 type WCHAR = u16;
 type APPIDPWSTR = *const WCHAR;
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum Error {
-    /// Window is not found
-    WindowNotFound,
-
-    /// Desktop with given ID is not found
-    DesktopNotFound,
-
-    /// Creationg of desktop failed
-    CreateDesktopFailed,
-
-    /// Remove desktop failed
-    RemoveDesktopFailed,
-
-    /// Unable to create service, ensure that explorer.exe is running
-    ClassNotRegistered,
-
-    /// Unable to connect to service
-    RpcServerNotAvailable,
-
-    /// Com is not initialized, call CoInitializeEx or CoIncrementMTAUsage
-    ComNotInitialized,
-
-    /// Com object not connected
-    ComObjectNotConnected,
-
-    /// Generic element not found
-    ComElementNotFound,
-
-    /// Some unhandled COM error
-    ComError(HRESULT),
-
-    /// This should not happen, this means that successful COM call allocated a
-    /// null pointer, in this case it is an error in the COM service, or it's
-    /// usage.
-    ComAllocatedNullPtr,
-
-    /// Borrow error
-    InternalBorrowError,
-}
-
 trait HRESULTHelpers {
     fn as_error(&self) -> Error;
     fn as_result(&self) -> Result<()>;
@@ -67,27 +33,7 @@ trait HRESULTHelpers {
 
 impl HRESULTHelpers for ::windows::core::HRESULT {
     fn as_error(&self) -> Error {
-        if self.0 == -2147221164 {
-            // 0x80040154
-            return Error::ClassNotRegistered;
-        }
-        if self.0 == -2147023174 {
-            // 0x800706BA
-            return Error::RpcServerNotAvailable;
-        }
-        if self.0 == -2147220995 {
-            // 0x800401FD
-            return Error::ComObjectNotConnected;
-        }
-        if self.0 == -2147319765 {
-            // 0x8002802B
-            return Error::ComElementNotFound;
-        }
-        if self.0 == -2147221008 {
-            // 0x800401F0
-            return Error::ComNotInitialized;
-        }
-        Error::ComError(self.clone())
+        error_from_hresult(*self)
     }
 
     fn as_result(&self) -> Result<()> {
@@ -161,56 +107,47 @@ impl<'a> TryFrom<&'a ComIn<'a, IVirtualDesktop>> for DesktopInternal {
 pub struct ComObjects {
     provider: RefCell<Option<Rc<IServiceProvider>>>,
     manager: RefCell<Option<Rc<IVirtualDesktopManager>>>,
-    manager_internal: RefCell<Option<Rc<IVirtualDesktopManagerInternal>>>,
+    manager_internal: RefCell<Option<Rc<ManagerInternal>>>,
     notification_service: RefCell<Option<Rc<IVirtualDesktopNotificationService>>>,
     pinned_apps: RefCell<Option<Rc<IVirtualDesktopPinnedApps>>>,
     view_collection: RefCell<Option<Rc<IApplicationViewCollection>>>,
 }
 
-fn retry_function<F, R>(com_objects: &ComObjects, f: F) -> Result<R>
-where
-    F: Fn() -> Result<R>,
-{
-    let mut value = f();
-    for _ in 0..3 {
-        match &value {
-            Err(er)
-                if er == &Error::ClassNotRegistered
-                    || er == &Error::RpcServerNotAvailable
-                    || er == &Error::ComObjectNotConnected
-                    || er == &Error::ComAllocatedNullPtr
-                    || er == &Error::ComNotInitialized =>
-            {
-                #[cfg(debug_assertions)]
-                log_output(&format!("Retry the function after {:?}", er));
-
-                if er == &Error::ComNotInitialized {
-                    let _ = unsafe { CoIncrementMTAUsage() };
-                }
+impl RetryBackend for ComObjects {
+    fn increment_mta_usage(&self) {
+        let _ = unsafe { CoIncrementMTAUsage() };
+    }
 
-                drop(value);
+    fn drop_services(&self) {
+        ComObjects::drop_services(self)
+    }
+}
 
-                // If private function is decorated, then this drop_services call
-                // will cause borrow issues.
-                com_objects.drop_services();
+/// Arguments of ComObjects functions that are recorded in the error context
+trait ErrorTarget {
+    fn add_to_context(&self, _desktop: &mut Option<Desktop>, _window: &mut Option<HWND>) {}
+}
 
-                value = f();
-            }
-            _ => {
-                break;
-            }
-        }
+impl ErrorTarget for &DesktopInternal {
+    fn add_to_context(&self, desktop: &mut Option<Desktop>, _window: &mut Option<HWND>) {
+        desktop.get_or_insert(Desktop::from(**self));
     }
+}
 
-    #[cfg(debug_assertions)]
-    if let Err(er) = &value {
-        log_output(&format!("Com_objects function failed with {:?}", er));
+impl ErrorTarget for &HWND {
+    fn add_to_context(&self, _desktop: &mut Option<Desktop>, window: &mut Option<HWND>) {
+        window.get_or_insert(**self);
     }
-
-    value
 }
 
-/// Safely reruns the function if it returns one of the recoverable errors
+impl ErrorTarget for &str {}
+impl ErrorTarget for u32 {}
+impl ErrorTarget for *mut c_void {}
+impl ErrorTarget for &IApplicationView {}
+
+/// Safely reruns the function if it returns one of the recoverable errors,
+/// according to the current `RetryPolicy`, the final error is given the
+/// function name and the desktop or window arguments as a context
 ///
 /// This should be applied to only public functions in ComObjects struct, having
 /// it in private functions is not necessary. Decorating private functions will
@@ -230,8 +167,22 @@ macro_rules! retry_function {(
         &$self_, $( $arg_name : $ArgTy ),*
     ) -> $RetTy
     {
-        retry_function(&$self_, || -> $RetTy {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::debug_span!("winvd", operation = stringify!($fname)).entered();
+        ::log::trace!(target: COM, "{}", stringify!($fname));
+
+        let started = std::time::Instant::now();
+        let result = run_with_retry(stringify!($fname), &get_retry_policy(), $self_, || -> $RetTy {
             $body
+        });
+        record_call(stringify!($fname), started.elapsed(), result.is_ok());
+        result.map_err(|er| {
+            #[allow(unused_mut)]
+            let mut desktop = None;
+            #[allow(unused_mut)]
+            let mut window = None;
+            $( $arg_name.add_to_context(&mut desktop, &mut window); )*
+            er.with_context(stringify!($fname), desktop, window)
         })
     }
 )}
@@ -292,26 +243,39 @@ impl ComObjects {
             .ok_or(Error::ComAllocatedNullPtr)
     }
 
-    fn get_manager_internal(&self) -> Result<Rc<IVirtualDesktopManagerInternal>> {
+    /// Interface profile of the running Windows build
+    pub(crate) fn get_profile(&self) -> Result<&'static BuildProfile> {
+        get_build_profile()
+    }
+
+    fn get_manager_internal(&self) -> Result<Rc<ManagerInternal>> {
         let mut manager_internal = self
             .manager_internal
             .try_borrow_mut()
             .map_err(|_| Error::InternalBorrowError)?;
         if manager_internal.is_none() {
+            let profile = self.get_profile()?;
             let mut obj = std::ptr::null_mut::<c_void>();
             let provider = self.get_provider()?;
             unsafe {
                 provider
                     .query_service(
                         &CLSID_VirtualDesktopManagerInternal,
-                        &IVirtualDesktopManagerInternal::IID,
+                        &profile.iids.manager_internal,
                         &mut obj,
                     )
                     .as_result()?;
             }
             assert_eq!(obj.is_null(), false);
             *manager_internal = Some(Rc::new(unsafe {
-                IVirtualDesktopManagerInternal::from_raw(obj)
+                match profile.layout {
+                    InterfaceLayout::Win11_23H2 => {
+                        ManagerInternal::Win11_23H2(IVirtualDesktopManagerInternal::from_raw(obj))
+                    }
+                    InterfaceLayout::Win11_24H2 => ManagerInternal::Win11_24H2(
+                        IVirtualDesktopManagerInternal24H2::from_raw(obj),
+                    ),
+                }
             }));
         }
         manager_internal
@@ -326,13 +290,14 @@ impl ComObjects {
             .try_borrow_mut()
             .map_err(|_| Error::InternalBorrowError)?;
         if notification_service.is_none() {
+            let profile = self.get_profile()?;
             let provider = self.get_provider()?;
             let mut obj = std::ptr::null_mut::<c_void>();
             unsafe {
                 provider
                     .query_service(
                         &CLSID_IVirtualNotificationService,
-                        &IVirtualDesktopNotificationService::IID,
+                        &profile.iids.notification_service,
                         &mut obj,
                     )
                     .as_result()?;
@@ -438,9 +403,8 @@ impl ComObjects {
                         .as_result()
                 };
 
-                #[cfg(debug_assertions)]
                 if let Err(er) = &res {
-                    log_output(&format!("is connected error: {:?} {}", er, out_count));
+                    ::log::debug!(target: COM, "is_connected failed: {} {}", er, out_count);
                 }
 
                 if out_count == 0 || res.is_err() {
@@ -452,6 +416,18 @@ impl ComObjects {
         }
     }
 
+    /// Resolve each service, and report which of them failed
+    pub(crate) fn probe_services(&self) -> ServiceStatus {
+        ServiceStatus {
+            provider: self.get_provider().map(|_| ()),
+            manager: self.get_manager().map(|_| ()),
+            manager_internal: self.get_manager_internal().map(|_| ()),
+            notification_service: self.get_notification_service().map(|_| ()),
+            pinned_apps: self.get_pinned_apps().map(|_| ()),
+            view_collection: self.get_view_collection().map(|_| ()),
+        }
+    }
+
     fn get_idesktops_array(&self) -> Result<IObjectArray> {
         let mut desktops = None;
         unsafe {
@@ -462,11 +438,25 @@ impl ComObjects {
         desktops.ok_or(Error::ComAllocatedNullPtr)
     }
 
+    /// Get desktop from the array, queried with the IID of the build profile
+    fn get_idesktop_at(&self, desktops: &IObjectArray, index: u32) -> Result<IVirtualDesktop> {
+        let iid = self.get_profile()?.iids.virtual_desktop;
+        unsafe {
+            let unknown: IUnknown = desktops.GetAt(index)?;
+            let mut obj = std::ptr::null_mut::<c_void>();
+            unknown.query(&iid, &mut obj).as_result()?;
+            if obj.is_null() {
+                return Err(Error::ComAllocatedNullPtr);
+            }
+            Ok(IVirtualDesktop::from_raw(obj))
+        }
+    }
+
     fn get_desktop_index_by_guid(&self, id: &GUID) -> Result<u32> {
         let desktops = self.get_idesktops_array()?;
         let count = unsafe { desktops.GetCount()? };
         for i in 0..count {
-            let desktop_id: GUID = get_idesktop_guid(&unsafe { desktops.GetAt(i)? })?;
+            let desktop_id: GUID = get_idesktop_guid(&self.get_idesktop_at(&desktops, i)?)?;
             if desktop_id == *id {
                 return Ok(i);
             }
@@ -477,10 +467,8 @@ impl ComObjects {
     fn get_desktop_guid_by_index(&self, id: u32) -> Result<GUID> {
         let desktops = self.get_idesktops_array()?;
         let count = unsafe { desktops.GetCount()? };
-        if id >= count {
-            return Err(Error::DesktopNotFound);
-        }
-        get_idesktop_guid(&unsafe { desktops.GetAt(id)? })
+        check_desktop_index(id, count)?;
+        get_idesktop_guid(&self.get_idesktop_at(&desktops, id)?)
     }
 
     fn get_idesktop(&self, desktop: &DesktopInternal) -> Result<IVirtualDesktop> {
@@ -488,16 +476,18 @@ impl ComObjects {
             DesktopInternal::Index(id) => {
                 let desktops = self.get_idesktops_array()?;
                 let count = unsafe { desktops.GetCount()? };
-                if *id >= count {
-                    return Err(Error::DesktopNotFound);
-                }
-                Ok(unsafe { desktops.GetAt(*id)? })
+                check_desktop_index(*id, count)?;
+                self.get_idesktop_at(&desktops, *id)
+                    .map_err(desktop_lookup_error)
             }
             DesktopInternal::Guid(id) => {
                 let manager = self.get_manager_internal()?;
                 let mut desktop = None;
                 unsafe {
-                    manager.find_desktop(id, &mut desktop).as_result()?;
+                    manager
+                        .find_desktop(id, &mut desktop)
+                        .as_result()
+                        .map_err(desktop_lookup_error)?;
                 }
                 desktop.ok_or(Error::DesktopNotFound)
             }
@@ -505,7 +495,10 @@ impl ComObjects {
                 let manager = self.get_manager_internal()?;
                 let mut desktop = None;
                 unsafe {
-                    manager.find_desktop(id, &mut desktop).as_result()?;
+                    manager
+                        .find_desktop(id, &mut desktop)
+                        .as_result()
+                        .map_err(desktop_lookup_error)?;
                 }
                 desktop.ok_or(Error::DesktopNotFound)
             }
@@ -522,13 +515,7 @@ impl ComObjects {
             self.get_manager_internal()?
                 .move_view_to_desktop(view, ComIn::new(&desktop))
                 .as_result()
-                .map_err(|e| {
-                    if e == Error::ComElementNotFound {
-                        Error::DesktopNotFound
-                    } else {
-                        e
-                    }
-                })?
+                .map_err(window_lookup_error)?
         }
         Ok(())
     }
@@ -539,13 +526,7 @@ impl ComObjects {
             self.get_view_collection()?
                 .get_view_for_hwnd(hwnd.clone(), &mut view)
                 .as_result()
-                .map_err(|er| {
-                    if er == Error::ComElementNotFound {
-                        Error::WindowNotFound
-                    } else {
-                        er
-                    }
-                })?
+                .map_err(window_lookup_error)?
         }
         view.ok_or(Error::WindowNotFound)
     }
@@ -561,6 +542,10 @@ impl ComObjects {
 
     #[apply(retry_function)]
     pub fn get_desktop_id(&self, desktop: &DesktopInternal) -> Result<GUID> {
+        self.get_desktop_guid(desktop)
+    }
+
+    fn get_desktop_guid(&self, desktop: &DesktopInternal) -> Result<GUID> {
         match desktop {
             DesktopInternal::Index(id) => self.get_desktop_guid_by_index(*id),
             DesktopInternal::Guid(guid) => Ok(*guid),
@@ -574,19 +559,49 @@ impl ComObjects {
         let count = unsafe { desktops.GetCount()? };
         let mut result = Vec::with_capacity(count as usize);
         for i in 0..count {
-            let desktop = unsafe { desktops.GetAt(i)? };
+            let desktop = self.get_idesktop_at(&desktops, i)?;
             let id = get_idesktop_guid(&desktop)?;
             result.push(DesktopInternal::IndexGuid(i, id));
         }
         Ok(result)
     }
 
+    #[apply(retry_function)]
+    pub fn get_snapshot(&self) -> Result<DesktopSnapshot> {
+        let desktops = self.get_idesktops_array()?;
+        let count = unsafe { desktops.GetCount()? };
+        let mut result = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let desktop = self.get_idesktop_at(&desktops, i)?;
+            let mut name = HSTRING::default();
+            let mut wallpaper = HSTRING::default();
+            unsafe {
+                desktop.get_name(&mut name).as_result()?;
+                desktop.get_wallpaper(&mut wallpaper).as_result()?;
+            }
+            result.push(DesktopState {
+                id: get_idesktop_guid(&desktop)?,
+                name: name.to_string(),
+                wallpaper: wallpaper.to_string(),
+            });
+        }
+        Ok(DesktopSnapshot {
+            desktops: result,
+            current: Some(self.get_current_desktop_guid()?),
+        })
+    }
+
     #[apply(retry_function)]
     pub fn register_for_notifications(
         &self,
         // notification: &IVirtualDesktopNotification,
         notification: *mut c_void, // IVirtualDesktopNotification raw pointer
     ) -> Result<u32> {
+        // Listener implements the compiled interface only
+        let profile = self.get_profile()?;
+        if profile.iids.notification != IVirtualDesktopNotification::IID {
+            return Err(Error::UnsupportedWindowsBuild(get_windows_build()?));
+        }
         let notification_service = self.get_notification_service()?;
 
         unsafe {
@@ -658,11 +673,7 @@ impl ComObjects {
             self.get_manager()?
                 .is_window_on_current_desktop(window.clone(), &mut value)
                 .as_result()
-                .map_err(|er| match er {
-                    // Window does not exist
-                    Error::ComElementNotFound => Error::WindowNotFound,
-                    _ => er,
-                })?;
+                .map_err(window_lookup_error)?;
             Ok(value)
         }
     }
@@ -673,6 +684,89 @@ impl ComObjects {
         self.move_view_to_desktop(ComIn::new(&view), desktop)
     }
 
+    /// Move the desktop to the index, other desktops are shifted
+    #[apply(retry_function)]
+    pub fn move_desktop(&self, desktop: &DesktopInternal, index: u32) -> Result<()> {
+        let desktop = self.get_idesktop(desktop)?;
+        unsafe {
+            self.get_manager_internal()?
+                .move_desktop(ComIn::new(&desktop), index)
+                .as_result()?
+        }
+        Ok(())
+    }
+
+    /// Windows on the desktop in the z-order, pinned windows are included
+    /// only if they were pinned on this desktop
+    #[apply(retry_function)]
+    pub fn get_desktop_windows(&self, desktop: &DesktopInternal) -> Result<Vec<HWND>> {
+        let id = self.get_desktop_guid(desktop)?;
+        Ok(self
+            .get_switcher_windows()?
+            .into_iter()
+            .filter(|(_, desktop)| *desktop == id)
+            .map(|(hwnd, _)| hwnd)
+            .collect())
+    }
+
+    /// Desktops of the windows in the z-order, the most recently used desktop
+    /// comes first, desktops without windows are not listed
+    #[apply(retry_function)]
+    pub fn get_recent_desktops(&self) -> Result<Vec<GUID>> {
+        let mut desktops: Vec<GUID> = Vec::new();
+        for (_, desktop) in self.get_switcher_windows()? {
+            if desktop != GUID::default() && !desktops.contains(&desktop) {
+                desktops.push(desktop);
+            }
+        }
+        Ok(desktops)
+    }
+
+    /// Windows shown in the task switcher and their desktops, pinned windows
+    /// have the desktop they were pinned on
+    #[apply(retry_function)]
+    pub fn get_all_windows(&self) -> Result<Vec<(HWND, GUID)>> {
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views(&mut views)
+                .as_result()?
+        }
+        self.get_switcher_views(views)
+    }
+
+    /// Windows shown in the task switcher and their desktops, in the z-order
+    fn get_switcher_windows(&self) -> Result<Vec<(HWND, GUID)>> {
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views_by_zorder(&mut views)
+                .as_result()?
+        }
+        self.get_switcher_views(views)
+    }
+
+    fn get_switcher_views(&self, views: Option<IObjectArray>) -> Result<Vec<(HWND, GUID)>> {
+        let views = views.ok_or(Error::ComAllocatedNullPtr)?;
+        let count = unsafe { views.GetCount()? };
+        let mut windows = Vec::new();
+        for i in 0..count {
+            let view: IApplicationView = unsafe { views.GetAt(i)? };
+            let mut show = 0;
+            let mut view_desktop = GUID::default();
+            let mut hwnd = HWND::default();
+            unsafe {
+                view.get_show_in_switchers(&mut show).as_result()?;
+                view.get_virtual_desktop_id(&mut view_desktop).as_result()?;
+                view.get_thumbnail_window(&mut hwnd).as_result()?;
+            }
+            if show != 0 {
+                windows.push((hwnd, view_desktop));
+            }
+        }
+        Ok(windows)
+    }
+
     #[apply(retry_function)]
     pub fn get_desktop_count(&self) -> Result<u32> {
         let manager = self.get_manager_internal()?;
@@ -690,11 +784,7 @@ impl ComObjects {
             self.get_manager()?
                 .get_desktop_by_window(window.clone(), &mut desktop)
                 .as_result()
-                .map_err(|er| match er {
-                    // Window does not exist
-                    Error::ComElementNotFound => Error::WindowNotFound,
-                    _ => er,
-                })?
+                .map_err(window_lookup_error)?
         };
         if desktop == GUID::default() {
             return Err(Error::WindowNotFound);
@@ -704,6 +794,10 @@ impl ComObjects {
 
     #[apply(retry_function)]
     pub fn get_current_desktop(&self) -> Result<DesktopInternal> {
+        Ok(DesktopInternal::Guid(self.get_current_desktop_guid()?))
+    }
+
+    fn get_current_desktop_guid(&self) -> Result<GUID> {
         let mut desktop = None;
         unsafe {
             self.get_manager_internal()?
@@ -711,8 +805,7 @@ impl ComObjects {
                 .as_result()?
         }
         let desktop = desktop.ok_or(Error::ComAllocatedNullPtr)?;
-        let id = get_idesktop_guid(&desktop)?;
-        Ok(DesktopInternal::Guid(id))
+        get_idesktop_guid(&desktop)
     }
 
     #[apply(retry_function)]
@@ -759,6 +852,18 @@ impl ComObjects {
         Ok(app_id)
     }
 
+    /// Application user model ID of the window
+    #[apply(retry_function)]
+    pub fn get_app_id(&self, window: &HWND) -> Result<String> {
+        let view = self.get_iapplication_view_for_hwnd(window)?;
+        let app_id = self.get_iapplication_id_for_view(&view)?;
+        if app_id.is_null() {
+            return Err(Error::ComAllocatedNullPtr);
+        }
+        let app_id = PCWSTR::from_raw(app_id);
+        Ok(String::from_utf16_lossy(unsafe { app_id.as_wide() }))
+    }
+
     #[apply(retry_function)]
     pub fn is_pinned_app(&self, window: &HWND) -> Result<bool> {
         let view = self.get_iapplication_view_for_hwnd(window)?;
@@ -792,6 +897,88 @@ impl ComObjects {
         Ok(())
     }
 
+    /// Top level windows of the application shown in the switchers
+    #[apply(retry_function)]
+    pub fn get_app_windows(&self, app_id: &str) -> Result<Vec<HWND>> {
+        let views = self.get_app_views(app_id)?;
+        let mut windows = Vec::with_capacity(views.len());
+        for view in views.iter() {
+            let mut hwnd = HWND::default();
+            unsafe { view.get_thumbnail_window(&mut hwnd).as_result()? };
+            windows.push(hwnd);
+        }
+        Ok(windows)
+    }
+
+    /// Move all windows of the application shown in the switchers, returns
+    /// the number of windows
+    #[apply(retry_function)]
+    pub fn move_app_to_desktop(&self, app_id: &str, desktop: &DesktopInternal) -> Result<u32> {
+        let views = self.get_app_views(app_id)?;
+        for view in views.iter() {
+            self.move_view_to_desktop(ComIn::new(view), desktop)?;
+        }
+        Ok(views.len() as u32)
+    }
+
+    #[apply(retry_function)]
+    pub fn is_pinned_app_id(&self, app_id: &str) -> Result<bool> {
+        let app_id = HSTRING::from(app_id);
+        unsafe {
+            let mut value = false;
+            self.get_pinned_apps()?
+                .is_app_pinned(app_id.as_ptr(), &mut value)
+                .as_result()?;
+            Ok(value)
+        }
+    }
+
+    #[apply(retry_function)]
+    pub fn pin_app_id(&self, app_id: &str) -> Result<()> {
+        let app_id = HSTRING::from(app_id);
+        unsafe {
+            self.get_pinned_apps()?
+                .pin_app(app_id.as_ptr())
+                .as_result()?;
+        }
+        Ok(())
+    }
+
+    #[apply(retry_function)]
+    pub fn unpin_app_id(&self, app_id: &str) -> Result<()> {
+        let app_id = HSTRING::from(app_id);
+        unsafe {
+            self.get_pinned_apps()?
+                .unpin_app(app_id.as_ptr())
+                .as_result()?;
+        }
+        Ok(())
+    }
+
+    fn get_app_views(&self, app_id: &str) -> Result<Vec<IApplicationView>> {
+        let app_id = HSTRING::from(app_id);
+        let mut views = None;
+        unsafe {
+            self.get_view_collection()?
+                .get_views_by_app_user_model_id(app_id.as_ptr(), &mut views)
+                .as_result()?
+        }
+        let views = views.ok_or(Error::ComAllocatedNullPtr)?;
+        let count = unsafe { views.GetCount()? };
+        let mut result = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let view: IApplicationView = unsafe { views.GetAt(i)? };
+
+            // Only the views shown in the switchers, like `get_switcher_views`
+            let mut show = 0;
+            unsafe { view.get_show_in_switchers(&mut show).as_result()? };
+            if show != 0 {
+                result.push(view);
+            }
+        }
+        Ok(result)
+    }
+
     #[apply(retry_function)]
     pub fn get_desktop_name(&self, desktop: &DesktopInternal) -> Result<String> {
         let desktop = self.get_idesktop(&desktop)?;
@@ -851,11 +1038,12 @@ thread_local! {
 ///
 /// Virtual Desktop COM Objects don't like to being called from different
 /// threads rapidly, something goes wrong. This function ensures that all COM
-/// calls are done in a single thread.
+/// calls are done in a single thread, either in the calling thread or in the
+/// worker thread, see `set_com_thread_mode`.
 pub fn with_com_objects<F, T>(f: F) -> Result<T>
 where
-    F: Fn(&ComObjects) -> Result<T> + 'static,
-    T: 'static,
+    F: Fn(&ComObjects) -> Result<T> + Send + 'static,
+    T: Send + 'static,
 {
     // return std::thread::scope(|env| {
     //     let com2 = ComObjects::new();
@@ -863,5 +1051,10 @@ where
     // });
 
     // return COM_OBJECTS.with(|c| run_function_and_retry(&f, &c));
+    if should_use_worker() {
+        // Retry policy set for this thread applies in the worker too
+        let policy = get_retry_policy();
+        return call_in_worker(move || with_retry_policy(policy, || COM_OBJECTS.with(|c| f(c))));
+    }
     return COM_OBJECTS.with(|c| f(&c));
 }
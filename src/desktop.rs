@@ -1,4 +1,7 @@
+use crate::duplicate::duplicate_desktop_with;
 use crate::interfaces::ComIn;
+use crate::remove::remove_desktop_with;
+use crate::window::focus_window;
 
 use super::comobjects::*;
 use super::{interfaces::IVirtualDesktop, *};
@@ -31,6 +34,16 @@ impl PartialEq for Desktop {
     }
 }
 
+impl std::fmt::Display for Desktop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            DesktopInternal::Index(index) => write!(f, "{}", index),
+            DesktopInternal::Guid(guid) => write!(f, "{:?}", guid),
+            DesktopInternal::IndexGuid(index, guid) => write!(f, "{} {:?}", index, guid),
+        }
+    }
+}
+
 // Impl from u32 to DesktopTest
 impl From<u32> for Desktop {
     fn from(index: u32) -> Self {
@@ -129,6 +142,37 @@ impl Desktop {
         let path_ = path.to_owned();
         with_com_objects(move |o| o.set_desktop_wallpaper(&internal, &path_))
     }
+
+    /// Remove the desktop, its windows are moved to the desktop chosen by
+    /// the strategy, returns that desktop
+    ///
+    /// The last remaining desktop can not be removed, this gives
+    /// `Error::CannotRemoveLastDesktop`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use winvd::*;
+    /// get_desktop(2).remove(FallbackStrategy::MostRecentlyUsed).unwrap();
+    /// ```
+    pub fn remove(&self, strategy: FallbackStrategy) -> Result<Desktop> {
+        let desktop = *self;
+        with_com_objects(move |o| remove_desktop_with(o, &desktop, strategy))
+    }
+
+    /// Create a desktop right after this one with the same wallpaper, and a
+    /// name derived from this one, e.g. "Build (2)"
+    pub fn duplicate(&self) -> Result<Desktop> {
+        self.duplicate_with(DuplicateOptions::default())
+            .map(|report| report.desktop)
+    }
+
+    /// Duplicate the desktop, see `duplicate`, and optionally pin the windows
+    /// of this desktop so that they are shown on the new one too
+    pub fn duplicate_with(&self, options: DuplicateOptions) -> Result<DuplicateReport> {
+        let desktop = *self;
+        with_com_objects(move |o| duplicate_desktop_with(o, &desktop, &options))
+    }
 }
 
 /// Get desktop by index or GUID
@@ -185,6 +229,25 @@ where
     with_com_objects(move |o| o.move_window_to_desktop(&hwnd, &desktop.into().into()))
 }
 
+/// Move window to desktop by index or GUID, switch to that desktop and bring
+/// the window to the foreground
+pub fn move_window_and_follow<T>(hwnd: HWND, desktop: T) -> Result<()>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| {
+        let desktop = desktop.into().into();
+        o.move_window_to_desktop(&hwnd, &desktop)?;
+        o.switch_desktop(&desktop)?;
+
+        // Switching activates the last active window of the desktop, so the
+        // focus is moved afterwards
+        focus_window(hwnd);
+        Ok(())
+    })
+}
+
 /// Create desktop
 pub fn create_desktop() -> Result<Desktop> {
     with_com_objects(|o| o.create_desktop().map(Desktop))
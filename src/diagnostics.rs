@@ -0,0 +1,218 @@
+/// Diagnostics report for troubleshooting
+///
+/// The report is meant to be attached to support tickets, its `Display`
+/// rendering is plain text that users can paste. With the `serde` feature
+/// the report can also be serialized.
+use crate::builds::{get_windows_version, select_build_profile, WindowsVersion};
+use crate::comobjects::with_com_objects;
+use crate::listener::notification_round_trip;
+use crate::{Error, ServiceStatus};
+use std::fmt;
+use windows::core::{w, PCWSTR};
+use windows::Win32::UI::WindowsAndMessaging::FindWindowW;
+
+/// Connection status of a single COM service
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ServiceDiagnostic {
+    pub name: String,
+
+    /// Error if the service did not connect
+    pub error: Option<String>,
+}
+
+/// Diagnostics collected by `diagnose`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DiagnosticsReport {
+    /// Version of the winvd crate
+    pub crate_version: String,
+
+    /// Windows build number, `None` if it could not be read
+    pub windows_build: Option<u32>,
+
+    /// Update revision of the build, `None` if it could not be read
+    pub windows_revision: Option<u32>,
+
+    /// Name of the interface profile, `None` if the build is not supported
+    pub build_profile: Option<String>,
+
+    /// Is the taskbar of explorer.exe found and the immersive shell created
+    pub explorer_reachable: bool,
+
+    pub services: Vec<ServiceDiagnostic>,
+
+    pub desktop_count: Option<u32>,
+    pub desktop_count_error: Option<String>,
+
+    /// Did registering and unregistering a notification succeed
+    pub notifications_round_trip: bool,
+    pub notifications_error: Option<String>,
+}
+
+impl DiagnosticsReport {
+    fn new(version: Option<WindowsVersion>) -> DiagnosticsReport {
+        DiagnosticsReport {
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+            windows_build: version.map(|v| v.build),
+            windows_revision: version.map(|v| v.revision),
+            build_profile: version
+                .and_then(select_build_profile)
+                .map(|p| p.name.to_owned()),
+            explorer_reachable: false,
+            services: Vec::new(),
+            desktop_count: None,
+            desktop_count_error: None,
+            notifications_round_trip: false,
+            notifications_error: None,
+        }
+    }
+}
+
+impl From<&ServiceStatus> for Vec<ServiceDiagnostic> {
+    fn from(status: &ServiceStatus) -> Self {
+        status
+            .iter()
+            .map(|(name, result)| ServiceDiagnostic {
+                name: name.to_owned(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for DiagnosticsReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "winvd {}", self.crate_version)?;
+        match (self.windows_build, self.windows_revision) {
+            (Some(build), Some(revision)) => write!(f, "Windows build: {}.{}", build, revision)?,
+            (Some(build), None) => write!(f, "Windows build: {}", build)?,
+            (None, _) => write!(f, "Windows build: unknown")?,
+        }
+        match &self.build_profile {
+            Some(profile) => writeln!(f, " ({})", profile)?,
+            None => writeln!(f, " (not supported)")?,
+        }
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        writeln!(f, "Explorer reachable: {}", yes_no(self.explorer_reachable))?;
+        writeln!(f, "Services:")?;
+        for service in self.services.iter() {
+            match &service.error {
+                None => writeln!(f, "  {}: connected", service.name)?,
+                Some(error) => writeln!(f, "  {}: {}", service.name, error)?,
+            }
+        }
+        match (&self.desktop_count, &self.desktop_count_error) {
+            (Some(count), _) => writeln!(f, "Desktop count: {}", count)?,
+            (None, Some(error)) => writeln!(f, "Desktop count: {}", error)?,
+            (None, None) => writeln!(f, "Desktop count: unknown")?,
+        }
+        match &self.notifications_error {
+            None => write!(
+                f,
+                "Notification round trip: {}",
+                yes_no(self.notifications_round_trip)
+            ),
+            Some(error) => write!(f, "Notification round trip: {}", error),
+        }
+    }
+}
+
+/// Collect diagnostics of the virtual desktop API, this does not fail, the
+/// errors are recorded in the report
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// println!("{}", diagnose());
+/// ```
+pub fn diagnose() -> DiagnosticsReport {
+    let mut report = DiagnosticsReport::new(get_windows_version().ok());
+    let taskbar = unsafe { FindWindowW(w!("Shell_TrayWnd"), PCWSTR::null()) };
+
+    let probe = with_com_objects(|o| {
+        let services = o.probe_services();
+        let desktop_count = o.get_desktop_count();
+        let notifications = notification_round_trip(o);
+        Ok((services, desktop_count, notifications))
+    });
+    match probe {
+        Ok((services, desktop_count, notifications)) => {
+            report.explorer_reachable = taskbar.0 != 0 && services.provider.is_ok();
+            report.services = (&services).into();
+            match desktop_count {
+                Ok(count) => report.desktop_count = Some(count),
+                Err(er) => report.desktop_count_error = Some(er.to_string()),
+            }
+            report.notifications_round_trip = notifications.is_ok();
+            report.notifications_error = notifications.err().as_ref().map(Error::to_string);
+        }
+        Err(er) => {
+            report.desktop_count_error = Some(er.to_string());
+            report.notifications_error = Some(er.to_string());
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_services_from_status() {
+        let status = ServiceStatus {
+            provider: Ok(()),
+            manager: Ok(()),
+            manager_internal: Err(Error::NoInterface),
+            notification_service: Ok(()),
+            pinned_apps: Ok(()),
+            view_collection: Ok(()),
+        };
+        let services: Vec<ServiceDiagnostic> = (&status).into();
+        assert_eq!(services.len(), 6);
+        assert_eq!(services[2].name, "manager_internal");
+        assert_eq!(services[2].error, Some(Error::NoInterface.to_string()));
+        assert!(services.iter().filter(|s| s.error.is_none()).count() == 5);
+    }
+
+    #[test]
+    fn test_report_display() {
+        let mut report = DiagnosticsReport::new(Some(WindowsVersion::new(22631, 3155)));
+        report.explorer_reachable = true;
+        report.services = vec![
+            ServiceDiagnostic {
+                name: "provider".to_owned(),
+                error: None,
+            },
+            ServiceDiagnostic {
+                name: "pinned_apps".to_owned(),
+                error: Some("access denied".to_owned()),
+            },
+        ];
+        report.desktop_count = Some(3);
+        report.notifications_round_trip = true;
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "winvd {}\n\
+                 Windows build: 22631.3155 (Windows 11 23H2)\n\
+                 Explorer reachable: yes\n\
+                 Services:\n  provider: connected\n  pinned_apps: access denied\n\
+                 Desktop count: 3\n\
+                 Notification round trip: yes",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+
+    #[test]
+    fn test_report_unsupported_build() {
+        let report = DiagnosticsReport::new(Some(WindowsVersion::new(19045, 4529)));
+        assert_eq!(report.build_profile, None);
+        assert!(report
+            .to_string()
+            .contains("Windows build: 19045.4529 (not supported)"));
+    }
+}
@@ -0,0 +1,177 @@
+/// Duplicating the configuration of a desktop
+use crate::backend::DesktopBackend;
+use crate::{create_desktop_with, Desktop, FailedStep, Result, Window};
+
+/// Options for `Desktop::duplicate_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DuplicateOptions {
+    /// Pin the windows of the source desktop, so that they are shown on the
+    /// new desktop too. Pinning is not per desktop, pinned windows are shown
+    /// on all desktops.
+    pub pin_windows: bool,
+}
+
+/// Result of `Desktop::duplicate_with`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateReport {
+    /// The new desktop
+    pub desktop: Desktop,
+
+    /// Windows that were pinned, windows pinned already are not listed
+    pub pinned: Vec<Window>,
+    pub failures: Vec<FailedStep>,
+}
+
+impl DuplicateReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub(crate) fn duplicate_desktop_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    desktop: &Desktop,
+    options: &DuplicateOptions,
+) -> Result<DuplicateReport> {
+    let id = backend.resolve(desktop)?;
+    let index = backend.index_of(id)?;
+    let names = backend
+        .desktop_ids()?
+        .into_iter()
+        .map(|d| backend.desktop_name(d))
+        .collect::<Result<Vec<_>>>()?;
+    let name = backend.desktop_name(id)?;
+    let wallpaper = backend.desktop_wallpaper(id)?;
+
+    // Unnamed desktops are shown with the default name by Windows
+    let mut builder = create_desktop_with().at_index(index + 1);
+    if !name.is_empty() {
+        builder = builder.name(&duplicate_name(&name, &names));
+    }
+    if !wallpaper.is_empty() {
+        builder = builder.wallpaper(&wallpaper);
+    }
+    let mut report = DuplicateReport {
+        desktop: builder.build_with(backend)?,
+        pinned: Vec::new(),
+        failures: Vec::new(),
+    };
+
+    if options.pin_windows {
+        for hwnd in backend.desktop_windows(id)? {
+            let pinned = backend.is_window_pinned(hwnd).and_then(|pinned| {
+                if pinned {
+                    Ok(false)
+                } else {
+                    backend.pin_window(hwnd).map(|_| true)
+                }
+            });
+            match pinned {
+                Ok(true) => report.pinned.push(Window(hwnd)),
+                Ok(false) => {}
+                Err(er) => report
+                    .failures
+                    .push(FailedStep::new(format!("pin window 0x{:X}", hwnd.0), er)),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Name for a copy of the desktop, e.g. "Build (2)" for "Build", and
+/// "Build (3)" for "Build (2)" if "Build (2)" exists already
+pub(crate) fn duplicate_name(name: &str, existing: &[String]) -> String {
+    let base = strip_copy_number(name);
+    (2..)
+        .map(|n| format!("{} ({})", base, n))
+        .find(|candidate| !existing.iter().any(|e| e == candidate))
+        .unwrap_or_default()
+}
+
+/// Name without the " (n)" suffix
+fn strip_copy_number(name: &str) -> &str {
+    let stripped = name
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .filter(|(_, number)| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()));
+    match stripped {
+        Some((base, _)) if !base.is_empty() => base,
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+    use windows::Win32::Foundation::HWND;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_duplicate_name() {
+        assert_eq!(duplicate_name("Build", &names(&["Build"])), "Build (2)");
+        assert_eq!(
+            duplicate_name("Build", &names(&["Build", "Build (2)"])),
+            "Build (3)"
+        );
+        assert_eq!(
+            duplicate_name("Build (2)", &names(&["Build", "Build (2)"])),
+            "Build (3)"
+        );
+        assert_eq!(duplicate_name("Build (x)", &[]), "Build (x) (2)");
+        assert_eq!(duplicate_name("(2)", &[]), "(2) (2)");
+        assert_eq!(duplicate_name("Build ()", &[]), "Build () (2)");
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let backend = SimulatedBackend::new(3)
+            .with_window(20, 2, false)
+            .with_window(21, 2, true);
+        let report =
+            duplicate_desktop_with(&backend, &Desktop::from(1), &DuplicateOptions::default())
+                .unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.desktop, Desktop::from(guid(4)));
+        assert!(report.pinned.is_empty());
+        assert_eq!(backend.names(), vec!["D1", "D2", "D2 (2)", "D3"]);
+        assert_eq!(backend.desktop_wallpaper(guid(4)).unwrap(), "C:\\wall2.jpg");
+        assert_eq!(backend.windows_on(2), vec![20, 21]);
+        assert_eq!(*backend.current.borrow(), guid(1));
+
+        duplicate_desktop_with(&backend, &Desktop::from(1), &DuplicateOptions::default()).unwrap();
+        assert_eq!(backend.names(), vec!["D1", "D2", "D2 (3)", "D2 (2)", "D3"]);
+    }
+
+    #[test]
+    fn test_duplicate_pin_windows() {
+        let backend = SimulatedBackend::new(2)
+            .with_window(10, 1, false)
+            .with_window(11, 1, true)
+            .with_window(12, 1, false)
+            .with_window(20, 2, false);
+        backend.fail("pin_window:12");
+        let options = DuplicateOptions { pin_windows: true };
+        let report = duplicate_desktop_with(&backend, &Desktop::from(0), &options).unwrap();
+        assert_eq!(report.pinned, vec![Window(HWND(10))]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].step, "pin window 0xC");
+        assert!(backend.is_window_pinned(HWND(10)).unwrap());
+        assert!(!backend.is_window_pinned(HWND(20)).unwrap());
+    }
+
+    #[test]
+    fn test_duplicate_unnamed() {
+        let backend = SimulatedBackend::new(1);
+        backend.set_desktop_name(guid(1), "").unwrap();
+        let report =
+            duplicate_desktop_with(&backend, &Desktop::from(0), &DuplicateOptions::default())
+                .unwrap();
+        assert_eq!(report.desktop, Desktop::from(guid(2)));
+        assert_eq!(backend.names(), vec!["", ""]);
+    }
+}
@@ -0,0 +1,436 @@
+/// Error type of the API
+use crate::Desktop;
+use std::fmt;
+use windows::core::HRESULT;
+use windows::Win32::Foundation::HWND;
+
+/// Errors of the API, use `kind()` to see through the context
+///
+/// Errors compare equal by their kind, the context is ignored, e.g.
+/// `Error::DesktopNotFound` equals to a `WithContext` error that has
+/// `DesktopNotFound` as its kind.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Window is not found
+    WindowNotFound,
+
+    /// Desktop with given ID is not found
+    DesktopNotFound,
+
+    /// Creationg of desktop failed
+    CreateDesktopFailed,
+
+    /// Remove desktop failed
+    RemoveDesktopFailed,
+
+    /// The only remaining desktop can not be removed
+    CannotRemoveLastDesktop,
+
+    /// Unable to create service, ensure that explorer.exe is running
+    ClassNotRegistered,
+
+    /// Unable to connect to service
+    RpcServerNotAvailable,
+
+    /// Com is not initialized, call CoInitializeEx or CoIncrementMTAUsage
+    ComNotInitialized,
+
+    /// Com object not connected
+    ComObjectNotConnected,
+
+    /// Generic element not found
+    ComElementNotFound,
+
+    /// Invalid argument given to the COM call
+    InvalidArgument,
+
+    /// Access denied, e.g. the calling process has higher integrity level
+    AccessDenied,
+
+    /// The COM object does not implement the interface, this happens when
+    /// the interface definitions do not match the Windows build
+    NoInterface,
+
+    /// The COM method is not implemented
+    NotImplemented,
+
+    /// The COM server is busy and rejected the call, try again later
+    ServerBusy,
+
+    /// Some unhandled COM error
+    ComError(HRESULT),
+
+    /// This should not happen, this means that successful COM call allocated a
+    /// null pointer, in this case it is an error in the COM service, or it's
+    /// usage.
+    ComAllocatedNullPtr,
+
+    /// Borrow error
+    InternalBorrowError,
+
+    /// There is no interface profile for the Windows build
+    UnsupportedWindowsBuild(u32),
+
+    /// Call to the COM worker thread did not finish in time
+    CallTimeout,
+
+    /// COM worker thread could not be started, or it panicked during the call
+    ComWorkerFailed,
+
+    /// Reading or writing a profile file failed, or the file is not a valid
+    /// profile
+    ProfileError(String),
+
+    /// Error with the failed operation, and the desktop or window it targeted
+    WithContext(Box<ErrorContext>),
+}
+
+/// Operation that failed, and the desktop or window it targeted
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorContext {
+    /// Name of the operation, e.g. `switch_desktop`
+    pub operation: &'static str,
+
+    /// Desktop given to the operation
+    pub desktop: Option<Desktop>,
+
+    /// Window given to the operation
+    pub window: Option<HWND>,
+
+    /// Error of the operation
+    pub error: Error,
+}
+
+/// Errors of the COM calls retried by the default `RetryPolicy`
+pub(crate) const RETRIED_ERRORS: [Error; 6] = [
+    Error::ClassNotRegistered,
+    Error::RpcServerNotAvailable,
+    Error::ComObjectNotConnected,
+    Error::ComAllocatedNullPtr,
+    Error::ComNotInitialized,
+    Error::ServerBusy,
+];
+
+impl Error {
+    /// Error without the context
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::WithContext(context) => context.error.kind(),
+            _ => self,
+        }
+    }
+
+    /// Outermost context of the error, if any
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::WithContext(context) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Is the error likely to go away by retrying, e.g. when explorer.exe is
+    /// restarting
+    ///
+    /// These are the errors retried by the default `RetryPolicy`, and
+    /// `CallTimeout`. The timeout happens on the calling thread after the
+    /// retries on the COM worker thread, so the policy never sees it, but
+    /// calling again may still succeed.
+    pub fn is_transient(&self) -> bool {
+        let kind = self.kind();
+        *kind == Error::CallTimeout || RETRIED_ERRORS.contains(kind)
+    }
+
+    /// Is the error caused by a desktop or window that does not exist
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self.kind(),
+            Error::WindowNotFound | Error::DesktopNotFound | Error::ComElementNotFound
+        )
+    }
+
+    /// HRESULT of the error, if it came from a COM call
+    ///
+    /// For errors mapped from several HRESULTs this gives the first one in
+    /// `HRESULT_ERRORS` table.
+    pub fn hresult(&self) -> Option<HRESULT> {
+        match self.kind() {
+            Error::ComError(hr) => Some(*hr),
+            kind => HRESULT_ERRORS
+                .iter()
+                .find(|(_, error)| error == kind)
+                .map(|(hr, _)| HRESULT(*hr as i32)),
+        }
+    }
+
+    /// Wrap the error with the operation and its targets
+    pub(crate) fn with_context(
+        self,
+        operation: &'static str,
+        desktop: Option<Desktop>,
+        window: Option<HWND>,
+    ) -> Error {
+        Error::WithContext(Box::new(ErrorContext {
+            operation,
+            desktop,
+            window,
+            error: self,
+        }))
+    }
+}
+
+/// Known HRESULTs and the errors they are mapped to, everything else is
+/// `Error::ComError`
+pub(crate) const HRESULT_ERRORS: &[(u32, Error)] = &[
+    // REGDB_E_CLASSNOTREG
+    (0x80040154, Error::ClassNotRegistered),
+    // RPC_S_SERVER_UNAVAILABLE
+    (0x800706BA, Error::RpcServerNotAvailable),
+    // RPC_S_CALL_FAILED
+    (0x800706BE, Error::RpcServerNotAvailable),
+    // RPC_E_SERVER_DIED
+    (0x80010007, Error::RpcServerNotAvailable),
+    // RPC_E_SERVER_DIED_DNE
+    (0x80010012, Error::RpcServerNotAvailable),
+    // CO_E_OBJNOTCONNECTED
+    (0x800401FD, Error::ComObjectNotConnected),
+    // RPC_E_DISCONNECTED
+    (0x80010108, Error::ComObjectNotConnected),
+    // TYPE_E_ELEMENTNOTFOUND
+    (0x8002802B, Error::ComElementNotFound),
+    // CO_E_NOTINITIALIZED
+    (0x800401F0, Error::ComNotInitialized),
+    // E_INVALIDARG
+    (0x80070057, Error::InvalidArgument),
+    // E_ACCESSDENIED
+    (0x80070005, Error::AccessDenied),
+    // E_NOINTERFACE
+    (0x80004002, Error::NoInterface),
+    // E_NOTIMPL
+    (0x80004001, Error::NotImplemented),
+    // RPC_E_CALL_REJECTED
+    (0x80010001, Error::ServerBusy),
+    // RPC_E_SERVERCALL_RETRYLATER
+    (0x8001010A, Error::ServerBusy),
+    // HRESULT_FROM_WIN32(ERROR_INVALID_WINDOW_HANDLE)
+    (0x80070578, Error::WindowNotFound),
+];
+
+/// Map the HRESULT to an error using `HRESULT_ERRORS` table
+pub(crate) fn error_from_hresult(hr: HRESULT) -> Error {
+    HRESULT_ERRORS
+        .iter()
+        .find(|(code, _)| *code as i32 == hr.0)
+        .map(|(_, error)| error.clone())
+        .unwrap_or(Error::ComError(hr))
+}
+
+/// Errors of looking up a desktop mean that the desktop does not exist
+pub(crate) fn desktop_lookup_error(error: Error) -> Error {
+    match error.kind() {
+        Error::ComElementNotFound | Error::InvalidArgument => Error::DesktopNotFound,
+        _ => error,
+    }
+}
+
+/// Errors of looking up a window mean that the window does not exist
+pub(crate) fn window_lookup_error(error: Error) -> Error {
+    match error.kind() {
+        Error::ComElementNotFound | Error::InvalidArgument => Error::WindowNotFound,
+        _ => error,
+    }
+}
+
+/// Index must be less than the desktop count
+pub(crate) fn check_desktop_index(index: u32, count: u32) -> Result<(), Error> {
+    if index >= count {
+        return Err(Error::DesktopNotFound);
+    }
+    Ok(())
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.kind(), other.kind()) {
+            (Error::ComError(a), Error::ComError(b)) => a == b,
+            (Error::UnsupportedWindowsBuild(a), Error::UnsupportedWindowsBuild(b)) => a == b,
+            (Error::ProfileError(a), Error::ProfileError(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hresult = self
+            .hresult()
+            .map(|hr| format!(" (HRESULT 0x{:08X})", hr.0 as u32))
+            .unwrap_or_default();
+        match self {
+            Error::WindowNotFound => write!(f, "window not found"),
+            Error::DesktopNotFound => write!(f, "desktop not found"),
+            Error::CreateDesktopFailed => write!(f, "creating desktop failed"),
+            Error::RemoveDesktopFailed => write!(f, "removing desktop failed"),
+            Error::CannotRemoveLastDesktop => write!(f, "cannot remove the last desktop"),
+            Error::ClassNotRegistered => write!(
+                f,
+                "class not registered{}, ensure that explorer.exe is running",
+                hresult
+            ),
+            Error::RpcServerNotAvailable => write!(f, "RPC server not available{}", hresult),
+            Error::ComNotInitialized => write!(f, "COM not initialized{}", hresult),
+            Error::ComObjectNotConnected => write!(f, "COM object not connected{}", hresult),
+            Error::ComElementNotFound => write!(f, "element not found{}", hresult),
+            Error::InvalidArgument => write!(f, "invalid argument{}", hresult),
+            Error::AccessDenied => write!(f, "access denied{}", hresult),
+            Error::NoInterface => write!(
+                f,
+                "interface not supported{}, the Windows build may not be supported",
+                hresult
+            ),
+            Error::NotImplemented => write!(f, "not implemented{}", hresult),
+            Error::ServerBusy => write!(f, "server is busy{}", hresult),
+            Error::ComError(_) => write!(f, "COM error{}", hresult),
+            Error::ComAllocatedNullPtr => write!(f, "COM call returned a null pointer"),
+            Error::InternalBorrowError => write!(f, "COM objects are already in use"),
+            Error::UnsupportedWindowsBuild(build) => {
+                write!(f, "Windows build {} is not supported", build)
+            }
+            Error::CallTimeout => write!(f, "call to the COM worker thread timed out"),
+            Error::ComWorkerFailed => write!(f, "COM worker thread failed"),
+            Error::ProfileError(message) => write!(f, "profile error: {}", message),
+            Error::WithContext(context) => write!(f, "{}", context),
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed", self.operation)?;
+        if let Some(desktop) = &self.desktop {
+            write!(f, ", desktop {}", desktop)?;
+        }
+        if let Some(window) = &self.window {
+            write!(f, ", window 0x{:X}", window.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::WithContext(context) => Some(&context.error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use windows::core::GUID;
+
+    #[test]
+    fn test_display_with_hresult() {
+        assert_eq!(
+            Error::ComError(HRESULT(0x80070057u32 as i32)).to_string(),
+            "COM error (HRESULT 0x80070057)"
+        );
+        assert_eq!(
+            Error::RpcServerNotAvailable.to_string(),
+            "RPC server not available (HRESULT 0x800706BA)"
+        );
+    }
+
+    #[test]
+    fn test_error_from_hresult() {
+        let cases = [
+            (0x80070057u32, Error::InvalidArgument),
+            (0x8002802B, Error::ComElementNotFound),
+            (0x80070005, Error::AccessDenied),
+            (0x80010108, Error::ComObjectNotConnected),
+            (0x80010012, Error::RpcServerNotAvailable),
+            (0x8001010A, Error::ServerBusy),
+            (0x80004005, Error::ComError(HRESULT(0x80004005u32 as i32))),
+        ];
+        for (hr, error) in cases {
+            assert_eq!(error_from_hresult(HRESULT(hr as i32)), error, "{:08X}", hr);
+        }
+    }
+
+    #[test]
+    fn test_hresult_table_round_trip() {
+        for (hr, error) in HRESULT_ERRORS {
+            let canonical = error.hresult().unwrap();
+            assert_eq!(&error_from_hresult(canonical), error, "{:08X}", hr);
+        }
+    }
+
+    #[test]
+    fn test_lookup_errors() {
+        assert_eq!(check_desktop_index(0, 1), Ok(()));
+        assert_eq!(check_desktop_index(99, 4), Err(Error::DesktopNotFound));
+        assert_eq!(
+            desktop_lookup_error(Error::ComElementNotFound),
+            Error::DesktopNotFound
+        );
+        assert_eq!(
+            desktop_lookup_error(Error::InvalidArgument),
+            Error::DesktopNotFound
+        );
+        assert_eq!(
+            desktop_lookup_error(Error::RpcServerNotAvailable),
+            Error::RpcServerNotAvailable
+        );
+        assert_eq!(
+            window_lookup_error(Error::ComElementNotFound),
+            Error::WindowNotFound
+        );
+    }
+
+    #[test]
+    fn test_profile_error_eq() {
+        let error = Error::ProfileError("missing field".to_owned());
+        assert_eq!(error, Error::ProfileError("missing field".to_owned()));
+        assert_ne!(error, Error::ProfileError("invalid GUID".to_owned()));
+    }
+
+    #[test]
+    fn test_context() {
+        let error = Error::DesktopNotFound.with_context("get_desktop_name", Some(3.into()), None);
+        assert_eq!(error, Error::DesktopNotFound);
+        assert_ne!(error, Error::WindowNotFound);
+        assert!(error.is_not_found());
+        assert!(!error.is_transient());
+        assert_eq!(error.to_string(), "get_desktop_name failed, desktop 3");
+        assert_eq!(
+            error.source().map(|e| e.to_string()),
+            Some("desktop not found".to_owned())
+        );
+
+        let guid = GUID::from_values(1, 2, 3, [4, 5, 6, 7, 8, 9, 10, 11]);
+        let error = Error::ComObjectNotConnected.with_context(
+            "move_window_to_desktop",
+            Some(guid.into()),
+            Some(HWND(0x1F)),
+        );
+        assert!(error.is_transient());
+        assert_eq!(
+            error.to_string(),
+            "move_window_to_desktop failed, desktop 00000001-0002-0003-0405-060708090A0B, window 0x1F"
+        );
+    }
+
+    #[test]
+    fn test_nested_context_kind() {
+        let error = Error::WindowNotFound
+            .with_context("get_desktop_by_window", None, Some(HWND(1)))
+            .with_context("is_window_on_desktop", Some(0.into()), Some(HWND(1)));
+        assert_eq!(error.kind(), &Error::WindowNotFound);
+        assert_eq!(
+            error.context().map(|c| c.operation),
+            Some("is_window_on_desktop")
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use crate::metrics::record_event;
 use crate::Desktop;
 use crate::DesktopEventThread;
 use crate::Error;
+use std::time::Duration;
 use windows::Win32::Foundation::HWND;
 
 #[derive(Clone)]
@@ -51,20 +53,21 @@ where
 
 impl<T> DesktopEventSender<T> {
     pub fn try_send(&self, event: T) {
-        match self {
-            DesktopEventSender::Std(sender) => {
-                let _ = sender.send(event);
-            }
+        let sent = match self {
+            DesktopEventSender::Std(sender) => sender.send(event).is_ok(),
 
             #[cfg(feature = "crossbeam-channel")]
-            DesktopEventSender::Crossbeam(sender) => {
-                let _ = sender.try_send(event);
-            }
+            DesktopEventSender::Crossbeam(sender) => sender.try_send(event).is_ok(),
 
             #[cfg(feature = "winit")]
-            DesktopEventSender::Winit(sender) => {
-                let _ = sender.send_event(event);
-            }
+            DesktopEventSender::Winit(sender) => sender.send_event(event).is_ok(),
+        };
+        record_event(sent);
+        if !sent {
+            ::log::warn!(
+                target: crate::log::LISTENER,
+                "Dropped event, the receiver is disconnected or full"
+            );
         }
     }
 }
@@ -113,6 +116,10 @@ pub enum DesktopEvent {
 /// Additionally you can pass crossbeam-channel sender, or winit eventloop proxy
 /// to the function.
 ///
+/// If the explorer.exe restarts, the listener is registered again. Changes that
+/// happened while the listener was disconnected are sent as synthesized events,
+/// see `diff_snapshots`.
+///
 pub fn listen_desktop_events<T, S>(sender: S) -> Result<DesktopEventThread, Error>
 where
     T: From<DesktopEvent> + Clone + Send + 'static,
@@ -120,3 +127,38 @@ where
 {
     DesktopEventThread::new(sender.into())
 }
+
+/// Create polling event thread, this is a fallback for `listen_desktop_events`
+/// in case the COM notifications do not work, e.g. on unsupported Windows
+/// builds.
+///
+/// The desktops are polled every `interval` and the differences are sent as
+/// `DesktopCreated`, `DesktopDestroyed`, `DesktopMoved`, `DesktopNameChanged`,
+/// `DesktopWallpaperChanged` and `DesktopChanged` events. `WindowChanged` is
+/// not sent, and changes that revert themselves within the interval are not
+/// noticed.
+///
+/// The fallback desktop of `DesktopDestroyed` event is not known, the current
+/// desktop is given instead.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+/// let _polling_thread =
+///     listen_desktop_events_polling(tx, std::time::Duration::from_millis(500));
+/// for item in rx {
+///    println!("{:?}", item);
+/// }
+/// ```
+pub fn listen_desktop_events_polling<T, S>(
+    sender: S,
+    interval: Duration,
+) -> Result<DesktopEventThread, Error>
+where
+    T: From<DesktopEvent> + Clone + Send + 'static,
+    S: Into<DesktopEventSender<T>> + Clone,
+{
+    DesktopEventThread::new_polling(sender.into(), interval)
+}
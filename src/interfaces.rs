@@ -324,14 +324,14 @@ pub unsafe trait IVirtualDesktop: IUnknown {
 
 #[windows_interface::interface("1841c6d7-4f9d-42c0-af41-8747538f10e5")]
 pub unsafe trait IApplicationViewCollection: IUnknown {
-    pub unsafe fn get_views(&self, out_views: *mut IObjectArray) -> HRESULT;
+    pub unsafe fn get_views(&self, out_views: *mut Option<IObjectArray>) -> HRESULT;
 
-    pub unsafe fn get_views_by_zorder(&self, out_views: *mut IObjectArray) -> HRESULT;
+    pub unsafe fn get_views_by_zorder(&self, out_views: *mut Option<IObjectArray>) -> HRESULT;
 
     pub unsafe fn get_views_by_app_user_model_id(
         &self,
         id: PCWSTR,
-        out_views: *mut IObjectArray,
+        out_views: *mut Option<IObjectArray>,
     ) -> HRESULT;
 
     pub unsafe fn get_view_for_hwnd(
@@ -500,6 +500,138 @@ pub unsafe trait IVirtualDesktopManagerInternal: IUnknown {
     pub unsafe fn update_wallpaper_for_all(&self, name: HSTRING) -> HRESULT;
 }
 
+/// Windows 11 24H2 version of `IVirtualDesktopManagerInternal` from
+/// 26100.2605, same IID but a method added after `switch_desktop`. Earlier
+/// 26100 revisions use the 23H2 layout.
+#[windows_interface::interface("53F5CA0B-158F-4124-900C-057158060B27")]
+pub unsafe trait IVirtualDesktopManagerInternal24H2: IUnknown {
+    pub unsafe fn get_desktop_count(&self, out_count: *mut UINT) -> HRESULT;
+
+    pub unsafe fn move_view_to_desktop(
+        &self,
+        view: ComIn<IApplicationView>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn can_move_view_between_desktops(
+        &self,
+        view: ComIn<IApplicationView>,
+        can_move: *mut i32,
+    ) -> HRESULT;
+
+    pub unsafe fn get_current_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn get_desktops(&self, out_desktops: *mut Option<IObjectArray>) -> HRESULT;
+
+    /// Get next or previous desktop
+    ///
+    /// Direction values:
+    /// 3 = Left direction
+    /// 4 = Right direction
+    pub unsafe fn get_adjacent_desktop(
+        &self,
+        in_desktop: ComIn<IVirtualDesktop>,
+        direction: UINT,
+        out_pp_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn switch_desktop(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn switch_desktop_and_move_foreground_view(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn create_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+
+    pub unsafe fn move_desktop(&self, in_desktop: ComIn<IVirtualDesktop>, index: UINT) -> HRESULT;
+
+    pub unsafe fn remove_desktop(
+        &self,
+        destroy_desktop: ComIn<IVirtualDesktop>,
+        fallback_desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn find_desktop(
+        &self,
+        guid: *const GUID,
+        out_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+
+    pub unsafe fn get_desktop_switch_include_exclude_views(
+        &self,
+        desktop: ComIn<IVirtualDesktop>,
+        out_pp_desktops1: *mut IObjectArray,
+        out_pp_desktops2: *mut IObjectArray,
+    ) -> HRESULT;
+
+    pub unsafe fn set_name(&self, desktop: ComIn<IVirtualDesktop>, name: HSTRING) -> HRESULT;
+    pub unsafe fn set_wallpaper(&self, desktop: ComIn<IVirtualDesktop>, name: HSTRING) -> HRESULT;
+    pub unsafe fn update_wallpaper_for_all(&self, name: HSTRING) -> HRESULT;
+}
+
+/// `IVirtualDesktopManagerInternal` with the method layout of the running
+/// Windows build, see `BuildProfile`
+#[allow(non_camel_case_types)]
+pub enum ManagerInternal {
+    Win11_23H2(IVirtualDesktopManagerInternal),
+    Win11_24H2(IVirtualDesktopManagerInternal24H2),
+}
+
+macro_rules! forward_manager_internal {(
+    $( pub unsafe fn $fname:ident(&self $(, $arg_name:ident : $ArgTy:ty )* $(,)? ) -> HRESULT; )*
+) => (
+    #[allow(dead_code)]
+    impl ManagerInternal {
+        $(
+            pub unsafe fn $fname(&self $(, $arg_name : $ArgTy )*) -> HRESULT {
+                match self {
+                    ManagerInternal::Win11_23H2(m) => m.$fname($( $arg_name ),*),
+                    ManagerInternal::Win11_24H2(m) => m.$fname($( $arg_name ),*),
+                }
+            }
+        )*
+    }
+)}
+
+forward_manager_internal! {
+    pub unsafe fn get_desktop_count(&self, out_count: *mut UINT) -> HRESULT;
+    pub unsafe fn move_view_to_desktop(
+        &self,
+        view: ComIn<IApplicationView>,
+        desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+    pub unsafe fn can_move_view_between_desktops(
+        &self,
+        view: ComIn<IApplicationView>,
+        can_move: *mut i32,
+    ) -> HRESULT;
+    pub unsafe fn get_current_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+    pub unsafe fn get_desktops(&self, out_desktops: *mut Option<IObjectArray>) -> HRESULT;
+    pub unsafe fn get_adjacent_desktop(
+        &self,
+        in_desktop: ComIn<IVirtualDesktop>,
+        direction: UINT,
+        out_pp_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+    pub unsafe fn switch_desktop(&self, desktop: ComIn<IVirtualDesktop>) -> HRESULT;
+    pub unsafe fn create_desktop(&self, out_desktop: *mut Option<IVirtualDesktop>) -> HRESULT;
+    pub unsafe fn move_desktop(&self, in_desktop: ComIn<IVirtualDesktop>, index: UINT) -> HRESULT;
+    pub unsafe fn remove_desktop(
+        &self,
+        destroy_desktop: ComIn<IVirtualDesktop>,
+        fallback_desktop: ComIn<IVirtualDesktop>,
+    ) -> HRESULT;
+    pub unsafe fn find_desktop(
+        &self,
+        guid: *const GUID,
+        out_desktop: *mut Option<IVirtualDesktop>,
+    ) -> HRESULT;
+    pub unsafe fn set_name(&self, desktop: ComIn<IVirtualDesktop>, name: HSTRING) -> HRESULT;
+    pub unsafe fn set_wallpaper(&self, desktop: ComIn<IVirtualDesktop>, name: HSTRING) -> HRESULT;
+    pub unsafe fn update_wallpaper_for_all(&self, name: HSTRING) -> HRESULT;
+}
+
 #[windows_interface::interface("4CE81583-1E4C-4632-A621-07A53543148F")]
 pub unsafe trait IVirtualDesktopPinnedApps: IUnknown {
     pub unsafe fn is_app_pinned(&self, app_id: PCWSTR, out_iss: *mut bool) -> HRESULT;
@@ -0,0 +1,478 @@
+/// Capturing the desktops of the windows, and restoring them after a reboot
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::{Error, FailedStep, Result, Window};
+use windows::core::GUID;
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::UI::WindowsAndMessaging::GetWindowRect;
+
+/// Window frame in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowFrame {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+/// Window of a `Layout`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayoutWindow {
+    #[cfg_attr(feature = "serde", serde(with = "window_handle"))]
+    pub window: Window,
+
+    /// Application user model ID
+    pub app_id: Option<String>,
+
+    /// Path of the executable
+    pub exe: Option<String>,
+    pub title: String,
+
+    /// Title with the numbers replaced by `*`, see `title_pattern`
+    pub title_pattern: String,
+    pub class_name: String,
+    #[cfg_attr(feature = "serde", serde(with = "option_guid_string"))]
+    pub desktop: Option<GUID>,
+    pub desktop_index: Option<u32>,
+    pub pinned: bool,
+    pub frame: Option<WindowFrame>,
+}
+
+/// Desktops of the windows, capture it with `capture_layout` and restore it
+/// with `restore_layout`
+///
+/// With the `serde` feature the layout can be serialized, e.g. to keep it
+/// over a reboot.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let layout = capture_layout().unwrap();
+/// // ... windows are moved around, or opened again after a reboot
+/// let report = restore_layout(&layout).unwrap();
+/// println!("Moved {} windows", report.moved.len());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Layout {
+    pub windows: Vec<LayoutWindow>,
+}
+
+/// Result of `restore_layout`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LayoutReport {
+    pub moved: Vec<Window>,
+    pub pinned: Vec<Window>,
+
+    /// Windows of the layout without a matching running window, by their
+    /// index in the layout
+    pub unmatched: Vec<usize>,
+    pub failures: Vec<FailedStep>,
+}
+
+impl LayoutReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Record the application, title, desktop, pinned state and frame of the
+/// windows shown in the task switcher
+pub fn capture_layout() -> Result<Layout> {
+    let windows = with_com_objects(|o| {
+        let ids = o.desktop_ids()?;
+        Ok(o.get_all_windows()?
+            .into_iter()
+            .map(|(hwnd, desktop)| {
+                let desktop = Some(desktop).filter(|d| *d != GUID::default());
+                let index = desktop.and_then(|d| ids.iter().position(|id| *id == d));
+                (
+                    hwnd,
+                    desktop,
+                    index.map(|i| i as u32),
+                    o.get_app_id(&hwnd).ok(),
+                    o.is_pinned_window(&hwnd).unwrap_or(false),
+                )
+            })
+            .collect::<Vec<_>>())
+    })?;
+    Ok(Layout {
+        windows: windows
+            .into_iter()
+            .map(|(hwnd, desktop, desktop_index, app_id, pinned)| {
+                let window = Window(hwnd);
+                let title = window.title();
+                LayoutWindow {
+                    window,
+                    app_id,
+                    exe: window.exe(),
+                    title_pattern: title_pattern(&title),
+                    title,
+                    class_name: window.class_name(),
+                    desktop,
+                    desktop_index,
+                    pinned,
+                    frame: window_frame(hwnd),
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Move the running windows to the desktops of the layout, and pin the
+/// windows that were pinned
+///
+/// Running windows are matched to the windows of the layout by the
+/// application, executable, class, title and frame, see `match_layout`. The
+/// desktop is found by the GUID, or by the index if the desktop is gone,
+/// without either the window gets a `DesktopNotFound` failure. Windows pinned
+/// now but not in the layout are left pinned.
+pub fn restore_layout(layout: &Layout) -> Result<LayoutReport> {
+    let running = capture_layout()?;
+    let layout = layout.clone();
+    with_com_objects(move |o| restore_layout_with(o, &layout, &running))
+}
+
+pub(crate) fn restore_layout_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    layout: &Layout,
+    running: &Layout,
+) -> Result<LayoutReport> {
+    let ids = backend.desktop_ids()?;
+    let matches = match_layout(&layout.windows, &running.windows);
+    let mut report = LayoutReport::default();
+    for (i, (saved, matched)) in layout.windows.iter().zip(matches.iter()).enumerate() {
+        let Some(current) = matched.map(|j| &running.windows[j]) else {
+            report.unmatched.push(i);
+            continue;
+        };
+        let hwnd = current.window.hwnd();
+        if saved.pinned {
+            if !current.pinned {
+                match backend.pin_window(hwnd) {
+                    Ok(()) => report.pinned.push(current.window),
+                    Err(er) => report
+                        .failures
+                        .push(FailedStep::new(format!("pin window 0x{:X}", hwnd.0), er)),
+                }
+            }
+            continue;
+        }
+        let target = saved.desktop.filter(|d| ids.contains(d)).or_else(|| {
+            saved
+                .desktop_index
+                .and_then(|i| ids.get(i as usize).copied())
+        });
+        let Some(target) = target else {
+            report.failures.push(FailedStep::new(
+                format!("find desktop of window 0x{:X}", hwnd.0),
+                Error::DesktopNotFound,
+            ));
+            continue;
+        };
+        if current.pinned || current.desktop == Some(target) {
+            continue;
+        }
+        match backend.move_window(hwnd, target) {
+            Ok(()) => report.moved.push(current.window),
+            Err(er) => report.failures.push(FailedStep::new(
+                format!("move window 0x{:X} to desktop {:?}", hwnd.0, target),
+                er,
+            )),
+        }
+    }
+    Ok(report)
+}
+
+/// Smallest score of a match
+const MIN_SCORE: u32 = 30;
+
+/// How likely the running window is the saved window, 0 if it can not be
+///
+/// The application or the executable must match, the class, title and frame
+/// add to the score.
+pub(crate) fn score(saved: &LayoutWindow, running: &LayoutWindow) -> u32 {
+    let mut score = 0;
+    match (&saved.app_id, &running.app_id) {
+        (Some(a), Some(b)) if a == b => score += 40,
+        (Some(_), Some(_)) => return 0,
+        _ => {}
+    }
+    match (&saved.exe, &running.exe) {
+        (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => score += 30,
+        (Some(_), Some(_)) => return 0,
+        _ => {}
+    }
+    if score == 0 {
+        return 0;
+    }
+    if saved.class_name == running.class_name {
+        score += 10;
+    }
+    if saved.title == running.title {
+        score += 30;
+    } else if saved.title_pattern == running.title_pattern {
+        score += 20;
+    }
+    if saved.frame.is_some() && saved.frame == running.frame {
+        score += 10;
+    }
+
+    // Handles are reused after a reboot, so the handle alone is not enough
+    if saved.window == running.window {
+        score += 5;
+    }
+    score
+}
+
+/// Index of the running window matched to each saved window
+///
+/// Pairs are taken greedily from the highest score, ties go to the earlier
+/// saved window and then to the earlier running window.
+pub(crate) fn match_layout(saved: &[LayoutWindow], running: &[LayoutWindow]) -> Vec<Option<usize>> {
+    let mut pairs: Vec<(u32, usize, usize)> = Vec::new();
+    for (i, s) in saved.iter().enumerate() {
+        for (j, r) in running.iter().enumerate() {
+            let score = score(s, r);
+            if score >= MIN_SCORE {
+                pairs.push((score, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    let mut matches = vec![None; saved.len()];
+    let mut taken = vec![false; running.len()];
+    for (_, i, j) in pairs {
+        if matches[i].is_none() && !taken[j] {
+            matches[i] = Some(j);
+            taken[j] = true;
+        }
+    }
+    matches
+}
+
+/// Title with the runs of digits replaced by `*`, e.g. "Inbox (12) - Mail"
+/// becomes "Inbox (*) - Mail"
+pub(crate) fn title_pattern(title: &str) -> String {
+    let mut pattern = String::with_capacity(title.len());
+    let mut in_digits = false;
+    for c in title.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                pattern.push('*');
+            }
+            in_digits = true;
+        } else {
+            pattern.push(c);
+            in_digits = false;
+        }
+    }
+    pattern
+}
+
+fn window_frame(hwnd: HWND) -> Option<WindowFrame> {
+    let mut rect = RECT::default();
+    unsafe { GetWindowRect(hwnd, &mut rect) }.ok()?;
+    Some(WindowFrame {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    })
+}
+
+#[cfg(feature = "serde")]
+mod window_handle {
+    use crate::Window;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use windows::Win32::Foundation::HWND;
+
+    pub fn serialize<S: Serializer>(window: &Window, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i64(window.0 .0 as i64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Window, D::Error> {
+        Ok(Window(HWND(i64::deserialize(deserializer)? as isize)))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod option_guid_string {
+    use crate::profile::guid_string;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use windows::core::GUID;
+
+    pub fn serialize<S: Serializer>(id: &Option<GUID>, serializer: S) -> Result<S::Ok, S::Error> {
+        match id {
+            Some(id) => guid_string::serialize(id, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<GUID>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(value) => guid_string::parse(&value)
+                .map(Some)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid GUID {}", value))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+
+    fn window(hwnd: isize, app_id: &str, title: &str, desktop: u32) -> LayoutWindow {
+        LayoutWindow {
+            window: Window(HWND(hwnd)),
+            app_id: Some(app_id.to_owned()),
+            exe: Some(format!("C:\\{}.exe", app_id)),
+            title: title.to_owned(),
+            title_pattern: title_pattern(title),
+            class_name: "Window".to_owned(),
+            desktop: Some(guid(desktop)),
+            desktop_index: Some(desktop - 1),
+            pinned: false,
+            frame: None,
+        }
+    }
+
+    #[test]
+    fn test_title_pattern() {
+        assert_eq!(title_pattern("Inbox (12) - Mail"), "Inbox (*) - Mail");
+        assert_eq!(title_pattern("v1.2.30"), "v*.*.*");
+        assert_eq!(title_pattern("Notepad"), "Notepad");
+    }
+
+    #[test]
+    fn test_score() {
+        let saved = window(1, "mail", "Inbox (3) - Mail", 2);
+        let mut running = window(9, "mail", "Inbox (5) - Mail", 1);
+        assert_eq!(score(&saved, &running), 100);
+        running.title = saved.title.clone();
+        assert_eq!(score(&saved, &running), 110);
+
+        // Different application can not match
+        let other = window(1, "code", "Inbox (3) - Mail", 2);
+        assert_eq!(score(&saved, &other), 0);
+
+        // Unknown application is matched by the executable
+        running.app_id = None;
+        assert_eq!(score(&saved, &running), 70);
+        running.exe = Some("C:\\MAIL.EXE".to_owned());
+        assert_eq!(score(&saved, &running), 70);
+        running.exe = None;
+        assert_eq!(score(&saved, &running), 0);
+    }
+
+    #[test]
+    fn test_match_layout() {
+        let saved = vec![
+            window(1, "mail", "Inbox - Mail", 2),
+            window(2, "code", "main.rs - Code", 3),
+            window(3, "code", "lib.rs - Code", 1),
+            window(4, "chat", "Chat", 2),
+        ];
+        let running = vec![
+            window(10, "code", "lib.rs - Code", 1),
+            window(11, "code", "other.rs - Code", 1),
+            window(12, "mail", "Inbox - Mail", 1),
+        ];
+        assert_eq!(
+            match_layout(&saved, &running),
+            vec![Some(2), Some(1), Some(0), None]
+        );
+    }
+
+    #[test]
+    fn test_restore_layout() {
+        let backend = SimulatedBackend::new(3)
+            .with_window(10, 1, false)
+            .with_window(11, 1, false)
+            .with_window(12, 1, false);
+        let mut pinned = window(3, "player", "Player", 1);
+        pinned.pinned = true;
+        let mut gone = window(4, "notes", "Notes", 9);
+        gone.desktop_index = None;
+        let layout = Layout {
+            windows: vec![
+                window(1, "mail", "Inbox - Mail", 2),
+                window(2, "code", "lib.rs - Code", 3),
+                pinned,
+                gone,
+            ],
+        };
+        let running = Layout {
+            windows: vec![
+                window(10, "code", "lib.rs - Code", 1),
+                window(11, "mail", "Inbox - Mail", 1),
+                window(12, "player", "Player", 1),
+            ],
+        };
+        let report = restore_layout_with(&backend, &layout, &running).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.moved, vec![Window(HWND(11)), Window(HWND(10))]);
+        assert_eq!(report.pinned, vec![Window(HWND(12))]);
+        assert_eq!(report.unmatched, vec![3]);
+        assert_eq!(backend.windows_on(2), vec![11]);
+        assert_eq!(backend.windows_on(3), vec![10]);
+        assert!(backend.is_window_pinned(HWND(12)).unwrap());
+    }
+
+    #[test]
+    fn test_restore_layout_by_index() {
+        let backend = SimulatedBackend::new(2).with_window(10, 1, false);
+        let mut saved = window(1, "mail", "Mail", 7);
+        saved.desktop_index = Some(1);
+        let layout = Layout {
+            windows: vec![saved],
+        };
+        let running = Layout {
+            windows: vec![window(10, "mail", "Mail", 1)],
+        };
+
+        // The saved desktop is gone, the window goes to the same index
+        let report = restore_layout_with(&backend, &layout, &running).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.moved, vec![Window(HWND(10))]);
+        assert_eq!(backend.windows_on(2), vec![10]);
+
+        let backend = SimulatedBackend::new(2).with_window(10, 1, false);
+        backend.fail("move_window:10");
+        let report = restore_layout_with(&backend, &layout, &running).unwrap();
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(
+            report.failures[0].step,
+            "move window 0xA to desktop 00000002-0000-0000-0000-000000000000"
+        );
+
+        // No desktop at the index either
+        let mut layout = layout;
+        layout.windows[0].desktop_index = Some(5);
+        let report = restore_layout_with(&backend, &layout, &running).unwrap();
+        assert!(report.unmatched.is_empty());
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].error, Error::DesktopNotFound);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_layout_json() {
+        let layout = Layout {
+            windows: vec![window(10, "mail", "Mail", 1)],
+        };
+        let json = serde_json::to_string(&layout).unwrap();
+        assert!(json.contains(r#""window":10"#));
+        assert!(json.contains(r#""desktop":"00000001-0000-0000-0000-000000000000""#));
+        assert_eq!(serde_json::from_str::<Layout>(&json).unwrap(), layout);
+    }
+}
@@ -8,21 +8,78 @@
 //! * Get desktop name by GUID `get_desktop(GUID(123...)).get_name()`
 //! * Switch to fifth desktop by index `switch_desktop(4)`
 //! * Get third desktop name `get_desktop(2).get_name()`
+//!
+//! # Threads
+//!
+//! By default each thread calling the API creates its own COM objects. To run
+//! all calls in a single worker thread, use
+//! `set_com_thread_mode(ComThreadMode::WorkerThread)`. Calls can be given a
+//! timeout with `with_call_timeout` or `set_default_call_timeout`.
+//!
+//! # Logging
+//!
+//! The crate logs through the `log` facade with `winvd::com`, `winvd::retry`
+//! and `winvd::listener` targets, and with the `tracing` feature each COM
+//! operation gets a span. `DebugOutputLogger` prints the messages to stdout and
+//! to the debugger.
+mod app;
+mod backend;
+mod builder;
+mod builds;
+mod capabilities;
 mod comobjects;
 mod desktop;
+mod diagnostics;
+mod duplicate;
+mod error;
 mod events;
 mod interfaces;
+mod layout;
 mod listener;
 mod log;
+mod merge;
+mod metrics;
+mod profile;
+mod remove;
+mod reorder;
+mod retry;
+mod rules;
+mod snapshot;
+mod spec;
+mod swap;
+mod window;
+mod worker;
 
 #[cfg(feature = "integration-tests")]
 #[cfg(test)]
 mod tests;
 
-pub use comobjects::Error;
+pub use crate::log::DebugOutputLogger;
+pub use app::*;
+pub use builder::*;
+pub use builds::*;
+pub use capabilities::*;
 pub use desktop::*;
+pub use diagnostics::*;
+pub use duplicate::{DuplicateOptions, DuplicateReport};
+pub use error::{Error, ErrorContext};
 pub use events::*;
+pub use layout::*;
 pub use listener::DesktopEventThread;
+pub use merge::*;
+pub use metrics::*;
+pub use profile::*;
+pub use remove::FallbackStrategy;
+pub use reorder::{reorder_desktops, sort_desktops_by};
+pub use retry::{
+    get_retry_policy, set_retry_policy, with_retry_policy, Backoff, RetryDecision, RetryPolicy,
+};
+pub use rules::*;
+pub use snapshot::*;
+pub use spec::*;
+pub use swap::*;
+pub use window::*;
+pub use worker::*;
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[macro_use]
@@ -1,5 +1,7 @@
+use std::cell::RefCell;
 use std::convert::TryInto;
 use std::pin::Pin;
+use std::rc::Rc;
 use std::time::Duration;
 
 use crate::comobjects::ComObjects;
@@ -7,9 +9,10 @@ use crate::interfaces::{
     ComIn, IApplicationView, IVirtualDesktop, IVirtualDesktopNotification,
     IVirtualDesktopNotification_Impl,
 };
-use crate::log::log_output;
+use crate::log::LISTENER;
+use crate::metrics::record_registration;
 use crate::DesktopEventSender;
-use crate::{DesktopEvent, Result};
+use crate::{DesktopEvent, DesktopSnapshot, Result};
 
 use windows::core::{Interface, HRESULT, HSTRING};
 use windows::Win32::Foundation::HWND;
@@ -17,14 +20,6 @@ use windows::Win32::System::Threading::{
     GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
 };
 
-// Log format macro
-macro_rules! log_format {
-    ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        $crate::log::log_output(&format!($($arg)*));
-    };
-}
-
 enum DekstopEventThreadMsg {
     Quit,
 }
@@ -49,50 +44,74 @@ impl DesktopEventThread {
         // Main notification thread, with STA message loop
         let notification_thread = std::thread::spawn(move || {
             let com_objects = ComObjects::new();
-            log_format!("Listener thread started {:?}", std::thread::current().id());
+            ::log::debug!(target: LISTENER, "Listener thread started {:?}", std::thread::current().id());
 
             // Set thread priority to time critical, explorer.exe really hates if your listener thread is slow
             let _ = unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) };
 
+            // State after the events sent so far, the listener keeps it up
+            // to date, and it's used to resynchronize after the listener is
+            // registered again
+            let last_snapshot = Rc::new(RefCell::new(com_objects.get_snapshot().ok()));
+
             // Create listener
-            let sender_new = sender.clone();
+            let forward =
+                |sender: DesktopEventSender<T>,
+                 last_snapshot: Rc<RefCell<Option<DesktopSnapshot>>>| {
+                    Box::new(move |event: DesktopEvent| {
+                        if let Some(snapshot) = last_snapshot.borrow_mut().as_mut() {
+                            snapshot.apply_event(&event);
+                        }
+                        sender.try_send(event.into());
+                    })
+                };
             let mut listener = VirtualDesktopNotificationWrapper::new(
                 &com_objects,
-                Box::new(move |event| {
-                    sender_new.try_send(event.into());
-                }),
+                forward(sender.clone(), last_snapshot.clone()),
             );
 
             loop {
                 let item = rx.recv_timeout(Duration::from_secs(3));
                 match item {
                     Ok(DekstopEventThreadMsg::Quit) => {
-                        log_output("Listener thread received quit message");
+                        ::log::debug!(target: LISTENER, "Listener thread received quit message");
                         break;
                     }
                     Err(_) => {
                         if !com_objects.is_connected() || listener.is_err() {
-                            log_output(
-                                "Listener is not connected, or failed to register, trying again",
+                            ::log::warn!(
+                                target: LISTENER,
+                                "Listener is not connected, or failed to register, trying again"
                             );
 
                             // Drop will unregister the old listener before the
                             // new one is created, this is required, read more
                             // from note-IVirtualDesktopNotification.md
                             drop(listener);
-                            let sender_new = sender.clone();
                             listener = VirtualDesktopNotificationWrapper::new(
                                 &com_objects,
-                                Box::new(move |event| {
-                                    sender_new.try_send(event.into());
-                                }),
+                                forward(sender.clone(), last_snapshot.clone()),
                             );
+
+                            // Send the changes that happened while disconnected
+                            if listener.is_ok() {
+                                if let Ok(snapshot) = com_objects.get_snapshot() {
+                                    let mut last_snapshot = last_snapshot.borrow_mut();
+                                    if let Some(last_snapshot) = last_snapshot.as_ref() {
+                                        for event in last_snapshot.diff(&snapshot) {
+                                            ::log::debug!(target: LISTENER, "Listener resynchronized {:?}", event);
+                                            sender.try_send(event.into());
+                                        }
+                                    }
+                                    *last_snapshot = Some(snapshot);
+                                }
+                            }
                         }
                     }
                 }
             }
 
-            log_format!("Listener thread finished {:?}", std::thread::current().id());
+            ::log::debug!(target: LISTENER, "Listener thread finished {:?}", std::thread::current().id());
         });
 
         // Store the new thread
@@ -102,6 +121,68 @@ impl DesktopEventThread {
         })
     }
 
+    /// Polling listener, takes a snapshot of desktops every `interval` and
+    /// sends the events synthesized from the difference to the previous one.
+    ///
+    /// This is a fallback for Windows builds where registering for COM
+    /// notifications does not work. `WindowChanged` events are not sent.
+    pub(crate) fn new_polling<T>(sender: DesktopEventSender<T>, interval: Duration) -> Result<Self>
+    where
+        T: From<DesktopEvent> + Clone + Send + 'static,
+    {
+        // Channel for quitting
+        let (tx, rx) = std::sync::mpsc::channel::<DekstopEventThreadMsg>();
+
+        let polling_thread = std::thread::spawn(move || {
+            let com_objects = ComObjects::new();
+            ::log::debug!(
+                target: LISTENER,
+                "Polling listener thread started {:?}",
+                std::thread::current().id()
+            );
+
+            let mut previous = com_objects.get_snapshot().ok();
+            loop {
+                let item = rx.recv_timeout(interval);
+                match item {
+                    Ok(DekstopEventThreadMsg::Quit) => {
+                        ::log::debug!(target: LISTENER, "Polling listener thread received quit message");
+                        break;
+                    }
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                        match com_objects.get_snapshot() {
+                            Ok(snapshot) => {
+                                if let Some(previous) = &previous {
+                                    for event in previous.diff(&snapshot) {
+                                        sender.try_send(event.into());
+                                    }
+                                }
+                                previous = Some(snapshot);
+                            }
+                            Err(er) => {
+                                // Keep the previous snapshot, so that changes
+                                // are reported once the snapshot succeeds
+                                ::log::warn!(target: LISTENER, "Polling listener snapshot failed: {}", er);
+                            }
+                        }
+                    }
+                }
+            }
+
+            ::log::debug!(
+                target: LISTENER,
+                "Polling listener thread finished {:?}",
+                std::thread::current().id()
+            );
+        });
+
+        Ok(DesktopEventThread {
+            thread_control_sender: Some(tx),
+            thread: Some(polling_thread),
+        })
+    }
+
     /// Stops the listener, and join the thread if it is still running, normally
     /// you don't need to call this as drop calls this automatically
     pub fn stop(&mut self) -> std::thread::Result<()> {
@@ -118,11 +199,8 @@ impl DesktopEventThread {
 
 impl Drop for DesktopEventThread {
     fn drop(&mut self) {
-        let _res = self.stop();
-
-        #[cfg(debug_assertions)]
-        if let Err(err) = _res {
-            log_format!("Could not stop listener thread {:?}", err);
+        if let Err(err) = self.stop() {
+            ::log::error!(target: LISTENER, "Could not stop listener thread {:?}", err);
         }
     }
 }
@@ -143,13 +221,16 @@ impl<'a> VirtualDesktopNotificationWrapper<'a> {
         let ptr: Pin<Box<IVirtualDesktopNotification>> =
             Pin::new(Box::new(VirtualDesktopNotification { sender }.into()));
         let raw_ptr = ptr.as_raw();
-        let cookie = com_objects.register_for_notifications(raw_ptr)?;
+        let cookie = com_objects.register_for_notifications(raw_ptr);
+        record_registration(cookie.is_ok());
+        let cookie = cookie?;
         let notification = Pin::new(Box::new(VirtualDesktopNotificationWrapper {
             com_objects,
             cookie,
             ptr,
         }));
-        log_format!(
+        ::log::info!(
+            target: LISTENER,
             "Registered notification {:?} {} {:?}",
             raw_ptr,
             notification.cookie,
@@ -162,15 +243,29 @@ impl<'a> VirtualDesktopNotificationWrapper<'a> {
 
 impl<'a> Drop for VirtualDesktopNotificationWrapper<'a> {
     fn drop(&mut self) {
-        log_format!(
+        ::log::info!(
+            target: LISTENER,
             "Unregistering notification {} {:?}",
             self.cookie,
             std::thread::current().id()
         );
 
         let cookie = self.cookie;
-        let _ = self.com_objects.unregister_for_notifications(cookie);
+        if let Err(er) = self.com_objects.unregister_for_notifications(cookie) {
+            ::log::warn!(target: LISTENER, "Unregistering notification failed: {}", er);
+        }
+    }
+}
+
+/// Register a notification that ignores the events, and unregister it
+pub(crate) fn notification_round_trip(com_objects: &ComObjects) -> Result<()> {
+    let notification: IVirtualDesktopNotification = VirtualDesktopNotification {
+        sender: Box::new(|_| {}),
     }
+    .into();
+    let cookie = com_objects.register_for_notifications(notification.as_raw())?;
+    ::log::debug!(target: LISTENER, "Notification round trip registered {}", cookie);
+    com_objects.unregister_for_notifications(cookie)
 }
 
 #[windows::core::implement(IVirtualDesktopNotification)]
@@ -182,8 +277,8 @@ fn eat_error<T>(func: impl FnOnce() -> Result<T>) -> Option<T> {
     let res = func();
     match res {
         Ok(v) => Some(v),
-        Err(_er) => {
-            log_format!("Error in listener: {:?}", _er);
+        Err(er) => {
+            ::log::warn!(target: LISTENER, "Error in listener: {}", er);
             None
         }
     }
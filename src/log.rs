@@ -1,9 +1,27 @@
-#[cfg(debug_assertions)]
+/// Logging through the `log` facade
+///
+/// Messages are emitted with these targets:
+///
+/// * `winvd::com` COM calls and their errors
+/// * `winvd::retry` retried and failed calls
+/// * `winvd::listener` listener threads, registering and unregistering the
+///   notifications, and dropped events
+/// * `winvd::rules` rules applied to the windows, and their errors
+///
+/// With the `tracing` feature each COM operation also gets a span. Nothing is
+/// printed unless the application installs a logger, `DebugOutputLogger`
+/// writes to stdout and `OutputDebugStringW`.
+use ::log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+pub(crate) const COM: &str = "winvd::com";
+pub(crate) const RETRY: &str = "winvd::retry";
+pub(crate) const LISTENER: &str = "winvd::listener";
+pub(crate) const RULES: &str = "winvd::rules";
+
 extern "system" {
     fn OutputDebugStringW(lpOutputString: windows::core::PCWSTR);
 }
 
-#[cfg(debug_assertions)]
 pub(crate) fn log_output(s: &str) {
     unsafe {
         println!("{}", s);
@@ -13,6 +31,49 @@ pub(crate) fn log_output(s: &str) {
     }
 }
 
-#[cfg(not(debug_assertions))]
-#[inline]
-pub(crate) fn log_output(_s: &str) {}
+/// Logger that prints to stdout and to the debugger with `OutputDebugStringW`,
+/// this is the output the crate used to have in debug builds
+#[derive(Debug)]
+pub struct DebugOutputLogger {
+    level: LevelFilter,
+}
+
+static DEBUG_OUTPUT_LOGGER: DebugOutputLogger = DebugOutputLogger {
+    level: LevelFilter::Trace,
+};
+
+impl DebugOutputLogger {
+    /// Install as the global logger for the `winvd` targets, fails if the
+    /// application has already installed a logger
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use winvd::*;
+    /// DebugOutputLogger::init(log::LevelFilter::Debug).unwrap();
+    /// ```
+    pub fn init(level: LevelFilter) -> std::result::Result<(), SetLoggerError> {
+        ::log::set_logger(&DEBUG_OUTPUT_LOGGER)?;
+        ::log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for DebugOutputLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.target().starts_with("winvd") && metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            log_output(&format!(
+                "[{} {}] {}",
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -0,0 +1,229 @@
+/// Merging one desktop into another
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::swap::move_windows;
+use crate::{Desktop, Error, FailedStep, Result, Window};
+use windows::core::GUID;
+
+/// Which desktop's property the merged desktop keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeepFrom {
+    /// Keep the property of the target desktop, this is the default
+    #[default]
+    Target,
+
+    /// Replace the property of the target with the one of the source
+    Source,
+}
+
+/// Options for `merge_desktops`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeOptions {
+    pub name: KeepFrom,
+    pub wallpaper: KeepFrom,
+}
+
+/// Result of `merge_desktops`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeReport {
+    pub source: Desktop,
+    pub target: Desktop,
+
+    /// Windows moved from the source to the target, in the z-order
+    pub moved: Vec<Window>,
+
+    /// Pinned windows are on all desktops, they are left pinned
+    pub kept_pinned: Vec<Window>,
+    pub source_removed: bool,
+    pub failures: Vec<FailedStep>,
+}
+
+impl MergeReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Move the windows of the `source` desktop to the `target` desktop, and
+/// remove the source
+///
+/// Windows are moved one by one in the z-order, and pinned windows are left
+/// pinned. The name and the wallpaper of the target are kept unless the
+/// options say otherwise. The source is removed even if some of the windows
+/// failed to move, as removing moves the rest of the windows to the target.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let options = MergeOptions {
+///     name: KeepFrom::Source,
+///     ..Default::default()
+/// };
+/// let report = merge_desktops(3, 0, options).unwrap();
+/// println!("Moved {} windows", report.moved.len());
+/// ```
+pub fn merge_desktops<T>(source: T, target: T, options: MergeOptions) -> Result<MergeReport>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| merge_desktops_with(o, &source.into(), &target.into(), &options))
+}
+
+pub(crate) fn merge_desktops_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    source: &Desktop,
+    target: &Desktop,
+    options: &MergeOptions,
+) -> Result<MergeReport> {
+    let source = backend.resolve(source)?;
+    let target = backend.resolve(target)?;
+    if source == target {
+        return Err(Error::InvalidArgument);
+    }
+    let mut report = MergeReport {
+        source: source.into(),
+        target: target.into(),
+        moved: Vec::new(),
+        kept_pinned: Vec::new(),
+        source_removed: false,
+        failures: Vec::new(),
+    };
+
+    let windows = backend.desktop_windows(source)?;
+    report.moved = move_windows(
+        backend,
+        &windows,
+        target,
+        &mut report.kept_pinned,
+        &mut report.failures,
+    );
+
+    // Properties are copied before the source is removed
+    if options.name == KeepFrom::Source {
+        copy_property(
+            "name",
+            source,
+            target,
+            |id| backend.desktop_name(id),
+            |id, value| backend.set_desktop_name(id, value),
+            &mut report.failures,
+        );
+    }
+    if options.wallpaper == KeepFrom::Source {
+        copy_property(
+            "wallpaper",
+            source,
+            target,
+            |id| backend.desktop_wallpaper(id),
+            |id, value| backend.set_desktop_wallpaper(id, value),
+            &mut report.failures,
+        );
+    }
+
+    match backend.remove_desktop(source, target) {
+        Ok(()) => report.source_removed = true,
+        Err(er) => report
+            .failures
+            .push(FailedStep::new(format!("remove desktop {:?}", source), er)),
+    }
+    Ok(report)
+}
+
+fn copy_property(
+    property: &str,
+    from: GUID,
+    to: GUID,
+    get: impl Fn(GUID) -> Result<String>,
+    set: impl Fn(GUID, &str) -> Result<()>,
+    failures: &mut Vec<FailedStep>,
+) {
+    if let Err(er) = get(from).and_then(|value| set(to, &value)) {
+        failures.push(FailedStep::new(
+            format!("copy {} to desktop {:?}", property, to),
+            er,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+    use windows::Win32::Foundation::HWND;
+
+    fn backend() -> SimulatedBackend {
+        SimulatedBackend::new(3)
+            .with_window(10, 1, false)
+            .with_window(20, 2, false)
+            .with_window(21, 2, true)
+            .with_window(22, 2, false)
+    }
+
+    #[test]
+    fn test_merge_keeps_target() {
+        let backend = backend();
+        let report = merge_desktops_with(
+            &backend,
+            &Desktop::from(1),
+            &Desktop::from(0),
+            &MergeOptions::default(),
+        )
+        .unwrap();
+        assert!(report.is_complete());
+        assert!(report.source_removed);
+        assert_eq!(report.moved, vec![Window(HWND(20)), Window(HWND(22))]);
+        assert_eq!(report.kept_pinned, vec![Window(HWND(21))]);
+        assert_eq!(backend.names(), vec!["D1", "D3"]);
+        assert_eq!(backend.desktop_wallpaper(guid(1)).unwrap(), "C:\\wall1.jpg");
+    }
+
+    #[test]
+    fn test_merge_keeps_source() {
+        let backend = backend();
+        let options = MergeOptions {
+            name: KeepFrom::Source,
+            wallpaper: KeepFrom::Source,
+        };
+        let report =
+            merge_desktops_with(&backend, &Desktop::from(1), &Desktop::from(2), &options).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(backend.names(), vec!["D1", "D2"]);
+        assert_eq!(backend.desktop_wallpaper(guid(3)).unwrap(), "C:\\wall2.jpg");
+        assert_eq!(backend.windows_on(3), vec![20, 21, 22]);
+    }
+
+    #[test]
+    fn test_merge_partial_failure() {
+        let backend = backend();
+        backend.fail("move_window:20");
+        backend.fail("remove_desktop");
+        let report = merge_desktops_with(
+            &backend,
+            &Desktop::from(1),
+            &Desktop::from(0),
+            &MergeOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(report.moved, vec![Window(HWND(22))]);
+        assert!(!report.source_removed);
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(backend.windows_on(2), vec![20, 21]);
+    }
+
+    #[test]
+    fn test_merge_into_itself() {
+        let backend = backend();
+        assert_eq!(
+            merge_desktops_with(
+                &backend,
+                &Desktop::from(1),
+                &Desktop::from(guid(2)),
+                &MergeOptions::default()
+            ),
+            Err(Error::InvalidArgument)
+        );
+    }
+}
@@ -0,0 +1,344 @@
+/// Counters and latency histograms of the COM operations and the listener
+///
+/// Every `ComObjects` call decorated with `retry_function` is recorded, read
+/// the values with `metrics()`.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the latency histogram buckets, the last bucket is
+/// unbounded
+pub const LATENCY_BUCKETS: [Duration; 11] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_millis(1000),
+    Duration::from_millis(2500),
+    Duration::from_millis(5000),
+];
+
+/// Latencies of the calls, including the retries
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LatencyHistogram {
+    /// Number of calls in each bucket of `LATENCY_BUCKETS`, and last the
+    /// number of calls slower than all buckets
+    pub counts: [u64; LATENCY_BUCKETS.len() + 1],
+    pub sum: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS.partition_point(|b| *b < latency);
+        self.counts[bucket] += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    /// Number of recorded calls
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Mean latency, `None` if nothing is recorded
+    pub fn mean(&self) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let nanos = self.sum.as_nanos() / count as u128;
+        Some(Duration::from_nanos(nanos as u64))
+    }
+
+    /// Cumulative counts of calls at most as slow as the bound, the last
+    /// bound is `None` for all calls
+    pub fn cumulative(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        let bounds = LATENCY_BUCKETS.iter().copied().map(Some).chain([None]);
+        bounds
+            .zip(self.counts.iter())
+            .scan(0, |total, (bound, count)| {
+                *total += count;
+                Some((bound, *total))
+            })
+    }
+}
+
+/// Statistics of a single operation
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OperationMetrics {
+    pub calls: u64,
+
+    /// Calls that returned an error after all attempts
+    pub errors: u64,
+
+    /// Retried attempts, the first attempt of a call is not counted
+    pub retries: u64,
+
+    pub latency: LatencyHistogram,
+}
+
+/// Statistics of the event listeners
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ListenerMetrics {
+    /// Successful notification registrations, including re-registrations
+    /// after explorer.exe restarts
+    pub registrations: u64,
+    pub registration_failures: u64,
+    pub events_sent: u64,
+
+    /// Events that could not be sent because the receiver was disconnected
+    /// or full
+    pub events_dropped: u64,
+}
+
+/// Snapshot of all metrics, get it with `metrics()`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Metrics {
+    /// Statistics by the operation name, e.g. `switch_desktop`
+    pub operations: BTreeMap<&'static str, OperationMetrics>,
+
+    /// Number of times the COM services were dropped to reconnect
+    pub reconnects: u64,
+
+    pub listener: ListenerMetrics,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    operations: BTreeMap::new(),
+    reconnects: 0,
+    listener: ListenerMetrics {
+        registrations: 0,
+        registration_failures: 0,
+        events_sent: 0,
+        events_dropped: 0,
+    },
+});
+
+fn update(f: impl FnOnce(&mut Metrics)) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        f(&mut metrics);
+    }
+}
+
+/// Get the snapshot of metrics recorded since the start or the last
+/// `reset_metrics`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// if let Some(switch) = metrics().operations.get("switch_desktop") {
+///     println!("switch_desktop mean {:?} max {:?}", switch.latency.mean(), switch.latency.max);
+/// }
+/// ```
+pub fn metrics() -> Metrics {
+    METRICS.lock().map(|m| m.clone()).unwrap_or_default()
+}
+
+/// Clear all recorded metrics
+pub fn reset_metrics() {
+    update(|m| *m = Metrics::default());
+}
+
+pub(crate) fn record_call(operation: &'static str, latency: Duration, ok: bool) {
+    update(|m| {
+        let op = m.operations.entry(operation).or_default();
+        op.calls += 1;
+        if !ok {
+            op.errors += 1;
+        }
+        op.latency.record(latency);
+    });
+}
+
+pub(crate) fn record_retry(operation: &'static str, reconnect: bool) {
+    update(|m| {
+        m.operations.entry(operation).or_default().retries += 1;
+        if reconnect {
+            m.reconnects += 1;
+        }
+    });
+}
+
+pub(crate) fn record_registration(ok: bool) {
+    update(|m| match ok {
+        true => m.listener.registrations += 1,
+        false => m.listener.registration_failures += 1,
+    });
+}
+
+pub(crate) fn record_event(sent: bool) {
+    update(|m| match sent {
+        true => m.listener.events_sent += 1,
+        false => m.listener.events_dropped += 1,
+    });
+}
+
+impl Metrics {
+    /// Render in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        type Counter = fn(&OperationMetrics) -> u64;
+        let counters: [(&str, &str, Counter); 3] = [
+            ("winvd_calls_total", "Calls of the operation", |o| o.calls),
+            (
+                "winvd_errors_total",
+                "Calls of the operation that failed",
+                |o| o.errors,
+            ),
+            (
+                "winvd_retries_total",
+                "Retried attempts of the operation",
+                |o| o.retries,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (operation, op) in self.operations.iter() {
+                let _ = writeln!(out, "{}{{operation=\"{}\"}} {}", name, operation, value(op));
+            }
+        }
+
+        let name = "winvd_call_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Latency of the operation", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (operation, op) in self.operations.iter() {
+            for (bound, count) in op.latency.cumulative() {
+                let le = bound
+                    .map(|b| b.as_secs_f64().to_string())
+                    .unwrap_or_else(|| "+Inf".to_owned());
+                let _ = writeln!(
+                    out,
+                    "{}_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    name, operation, le, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_sum{{operation=\"{}\"}} {}",
+                name,
+                operation,
+                op.latency.sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "{}_count{{operation=\"{}\"}} {}",
+                name,
+                operation,
+                op.latency.count()
+            );
+        }
+
+        let totals = [
+            (
+                "winvd_reconnects_total",
+                "COM services dropped to reconnect",
+                self.reconnects,
+            ),
+            (
+                "winvd_listener_registrations_total",
+                "Successful notification registrations",
+                self.listener.registrations,
+            ),
+            (
+                "winvd_listener_registration_failures_total",
+                "Failed notification registrations",
+                self.listener.registration_failures,
+            ),
+            (
+                "winvd_listener_events_sent_total",
+                "Events sent by the listeners",
+                self.listener.events_sent,
+            ),
+            (
+                "winvd_listener_events_dropped_total",
+                "Events dropped by the listeners",
+                self.listener.events_dropped,
+            ),
+        ];
+        for (name, help, value) in totals {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.mean(), None);
+        histogram.record(Duration::from_micros(500));
+        histogram.record(Duration::from_millis(5));
+        histogram.record(Duration::from_millis(300));
+        histogram.record(Duration::from_secs(10));
+        assert_eq!(histogram.count(), 4);
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[1], 1, "Bounds are inclusive");
+        assert_eq!(histogram.counts[7], 1);
+        assert_eq!(histogram.counts[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(histogram.max, Duration::from_secs(10));
+
+        let cumulative: Vec<u64> = histogram.cumulative().map(|(_, c)| c).collect();
+        assert_eq!(cumulative, vec![1, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 4]);
+    }
+
+    #[test]
+    fn test_mean_over_u32_calls() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.counts[0] = u32::MAX as u64 + 1;
+        histogram.sum = Duration::from_micros(u32::MAX as u64 + 1);
+        assert_eq!(histogram.mean(), Some(Duration::from_micros(1)));
+    }
+
+    #[test]
+    fn test_record_call() {
+        record_call("test_record_call", Duration::from_millis(2), true);
+        record_call("test_record_call", Duration::from_millis(4), false);
+        record_retry("test_record_call", true);
+        let op = metrics().operations["test_record_call"].clone();
+        assert_eq!(op.calls, 2);
+        assert_eq!(op.errors, 1);
+        assert_eq!(op.retries, 1);
+        assert_eq!(op.latency.mean(), Some(Duration::from_millis(3)));
+        assert!(metrics().reconnects >= 1);
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let mut metrics = Metrics::default();
+        let op = metrics.operations.entry("switch_desktop").or_default();
+        op.calls = 1;
+        op.latency.record(Duration::from_millis(20));
+        metrics.listener.events_dropped = 2;
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("# TYPE winvd_calls_total counter\n"));
+        assert!(text.contains("winvd_calls_total{operation=\"switch_desktop\"} 1\n"));
+        assert!(text.contains(
+            "winvd_call_duration_seconds_bucket{operation=\"switch_desktop\",le=\"0.01\"} 0\n"
+        ));
+        assert!(text.contains(
+            "winvd_call_duration_seconds_bucket{operation=\"switch_desktop\",le=\"0.025\"} 1\n"
+        ));
+        assert!(text.contains(
+            "winvd_call_duration_seconds_bucket{operation=\"switch_desktop\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(
+            text.contains("winvd_call_duration_seconds_count{operation=\"switch_desktop\"} 1\n")
+        );
+        assert!(text.contains("winvd_listener_events_dropped_total 2\n"));
+    }
+}
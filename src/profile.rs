@@ -0,0 +1,294 @@
+/// Saving and restoring the names and wallpapers of the desktops
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::{Desktop, DesktopSnapshot, FailedStep, Result};
+use windows::core::GUID;
+
+/// Names and wallpapers of the desktops in the index order
+///
+/// Windows sometimes resets the names and wallpapers, e.g. after an update
+/// or an explorer.exe restart. Capture the profile while they are right, and
+/// restore it afterwards. With the `serde` feature the profile can be saved
+/// to a JSON file with `save_profile`, and restored with `restore_profile`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Profile {
+    pub desktops: Vec<ProfileDesktop>,
+}
+
+/// Desktop of a `Profile`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProfileDesktop {
+    #[cfg_attr(feature = "serde", serde(with = "guid_string"))]
+    pub id: GUID,
+    pub name: String,
+    pub wallpaper: String,
+}
+
+/// Result of `Profile::restore`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RestoreReport {
+    pub renamed: Vec<Desktop>,
+    pub wallpapers_set: Vec<Desktop>,
+
+    /// Desktops of the profile without a matching desktop, by their index in
+    /// the profile
+    pub unmatched: Vec<usize>,
+    pub failures: Vec<FailedStep>,
+}
+
+impl RestoreReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl Profile {
+    /// Profile of the current desktops
+    pub fn capture() -> Result<Profile> {
+        with_com_objects(|o| Ok(Profile::from(&o.snapshot()?)))
+    }
+
+    /// Set the names and wallpapers of the desktops, see `match_profile` for
+    /// how the desktops are matched
+    pub fn restore(&self) -> Result<RestoreReport> {
+        let profile = self.clone();
+        with_com_objects(move |o| restore_profile_with(o, &profile))
+    }
+}
+
+impl From<&DesktopSnapshot> for Profile {
+    fn from(snapshot: &DesktopSnapshot) -> Self {
+        Profile {
+            desktops: snapshot
+                .desktops
+                .iter()
+                .map(|d| ProfileDesktop {
+                    id: d.id,
+                    name: d.name.clone(),
+                    wallpaper: d.wallpaper.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Write the profile of the current desktops to a JSON file
+#[cfg(feature = "serde")]
+pub fn save_profile<P: AsRef<std::path::Path>>(path: P) -> Result<Profile> {
+    let profile = Profile::capture()?;
+    let json = serde_json::to_string_pretty(&profile)
+        .map_err(|er| crate::Error::ProfileError(er.to_string()))?;
+    std::fs::write(path, json).map_err(|er| crate::Error::ProfileError(er.to_string()))?;
+    Ok(profile)
+}
+
+/// Read the profile from a JSON file written by `save_profile`, and restore it
+#[cfg(feature = "serde")]
+pub fn restore_profile<P: AsRef<std::path::Path>>(path: P) -> Result<RestoreReport> {
+    let json =
+        std::fs::read_to_string(path).map_err(|er| crate::Error::ProfileError(er.to_string()))?;
+    let profile: Profile =
+        serde_json::from_str(&json).map_err(|er| crate::Error::ProfileError(er.to_string()))?;
+    profile.restore()
+}
+
+pub(crate) fn restore_profile_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    profile: &Profile,
+) -> Result<RestoreReport> {
+    let snapshot = backend.snapshot()?;
+    let matches = match_profile(profile, &snapshot);
+    let mut report = RestoreReport::default();
+    for (i, (saved, matched)) in profile.desktops.iter().zip(matches.iter()).enumerate() {
+        let Some(existing) = matched.map(|j| &snapshot.desktops[j]) else {
+            report.unmatched.push(i);
+            continue;
+        };
+        if existing.name != saved.name {
+            match backend.set_desktop_name(existing.id, &saved.name) {
+                Ok(()) => report.renamed.push(existing.id.into()),
+                Err(er) => report.failures.push(FailedStep::new(
+                    format!("set name of desktop {:?}", existing.id),
+                    er,
+                )),
+            }
+        }
+        if existing.wallpaper != saved.wallpaper && !saved.wallpaper.is_empty() {
+            match backend.set_desktop_wallpaper(existing.id, &saved.wallpaper) {
+                Ok(()) => report.wallpapers_set.push(existing.id.into()),
+                Err(er) => report.failures.push(FailedStep::new(
+                    format!("set wallpaper of desktop {:?}", existing.id),
+                    er,
+                )),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Index of the snapshot desktop matched to each profile desktop
+///
+/// Desktops are matched by the GUID first. The rest are matched by the
+/// position, the profile desktop at index `i` gets the desktop at index `i`
+/// if it is not matched by the GUID to another profile desktop.
+pub(crate) fn match_profile(profile: &Profile, snapshot: &DesktopSnapshot) -> Vec<Option<usize>> {
+    let mut matches: Vec<Option<usize>> = profile
+        .desktops
+        .iter()
+        .map(|saved| snapshot.index_of(&saved.id))
+        .collect();
+    for i in 0..matches.len() {
+        if matches[i].is_none() && i < snapshot.desktops.len() && !matches.contains(&Some(i)) {
+            matches[i] = Some(i);
+        }
+    }
+    matches
+}
+
+/// GUIDs in the "00000000-0000-0000-0000-000000000000" form
+#[cfg(feature = "serde")]
+pub(crate) mod guid_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use windows::core::GUID;
+
+    pub fn serialize<S: Serializer>(id: &GUID, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", id))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<GUID, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        parse(&value).ok_or_else(|| serde::de::Error::custom(format!("invalid GUID {}", value)))
+    }
+
+    pub(crate) fn parse(value: &str) -> Option<GUID> {
+        let value = value.trim_start_matches('{').trim_end_matches('}');
+        let dashes = value.char_indices().filter(|(_, c)| *c == '-');
+        if value.len() != 36 || !dashes.map(|(i, _)| i).eq([8, 13, 18, 23]) {
+            return None;
+        }
+        let hex: String = value.chars().filter(|c| *c != '-').collect();
+        u128::from_str_radix(&hex, 16).ok().map(GUID::from_u128)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+    use crate::DesktopState;
+
+    fn profile(desktops: &[(u32, &str, &str)]) -> Profile {
+        Profile {
+            desktops: desktops
+                .iter()
+                .map(|(n, name, wallpaper)| ProfileDesktop {
+                    id: guid(*n),
+                    name: name.to_string(),
+                    wallpaper: wallpaper.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn snapshot(ids: &[u32]) -> DesktopSnapshot {
+        DesktopSnapshot {
+            desktops: ids
+                .iter()
+                .map(|n| DesktopState {
+                    id: guid(*n),
+                    name: String::new(),
+                    wallpaper: String::new(),
+                })
+                .collect(),
+            current: None,
+        }
+    }
+
+    #[test]
+    fn test_match_profile() {
+        let saved = profile(&[(1, "A", ""), (2, "B", ""), (3, "C", "")]);
+        assert_eq!(
+            match_profile(&saved, &snapshot(&[1, 2, 3])),
+            vec![Some(0), Some(1), Some(2)]
+        );
+
+        // Reordered desktops are matched by the GUID
+        assert_eq!(
+            match_profile(&saved, &snapshot(&[3, 1, 2])),
+            vec![Some(1), Some(2), Some(0)]
+        );
+
+        // New GUIDs after a reset are matched by the position
+        assert_eq!(
+            match_profile(&saved, &snapshot(&[7, 8])),
+            vec![Some(0), Some(1), None]
+        );
+
+        // Position taken by a GUID match is not reused
+        assert_eq!(
+            match_profile(&saved, &snapshot(&[2, 9, 8])),
+            vec![None, Some(0), Some(2)]
+        );
+    }
+
+    #[test]
+    fn test_restore_profile() {
+        let backend = SimulatedBackend::new(3);
+        let saved = profile(&[
+            (2, "Code", "C:\\code.jpg"),
+            (8, "Mail", "C:\\mail.jpg"),
+            (9, "Chat", ""),
+            (10, "Extra", ""),
+        ]);
+        let report = restore_profile_with(&backend, &saved).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(backend.names(), vec!["D1", "Code", "Chat"]);
+        assert_eq!(backend.desktop_wallpaper(guid(2)).unwrap(), "C:\\code.jpg");
+        assert_eq!(backend.desktop_wallpaper(guid(3)).unwrap(), "C:\\wall3.jpg");
+        assert_eq!(report.unmatched, vec![1, 3]);
+        assert_eq!(
+            report.renamed,
+            vec![Desktop::from(guid(2)), Desktop::from(guid(3))]
+        );
+        assert_eq!(report.wallpapers_set, vec![Desktop::from(guid(2))]);
+    }
+
+    #[test]
+    fn test_restore_profile_failure() {
+        let backend = SimulatedBackend::new(2);
+        backend.fail("set_desktop_name");
+        let report =
+            restore_profile_with(&backend, &profile(&[(1, "A", ""), (2, "B", "")])).unwrap();
+        assert_eq!(report.failures.len(), 2);
+        assert!(report.renamed.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_profile_json() {
+        let saved = profile(&[(1, "A", "C:\\a.jpg")]);
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(
+            json,
+            r#"{"desktops":[{"id":"00000001-0000-0000-0000-000000000000","name":"A","wallpaper":"C:\\a.jpg"}]}"#
+        );
+        assert_eq!(serde_json::from_str::<Profile>(&json).unwrap(), saved);
+
+        let id = GUID::from_values(
+            0x1A2B3C4D,
+            0x5E6F,
+            0x7081,
+            [0x92, 0xA3, 0xB4, 0xC5, 0xD6, 0xE7, 0xF8, 0x09],
+        );
+        assert_eq!(guid_string::parse(&format!("{:?}", id)), Some(id));
+        assert_eq!(guid_string::parse(&format!("{{{:?}}}", id)), Some(id));
+        assert_eq!(guid_string::parse("1A2B3C4D-5E6F-7081-92A3"), None);
+        assert_eq!(
+            guid_string::parse("1A2B3C4D5-E6F-7081-92A3-B4C5D6E7F809"),
+            None
+        );
+    }
+}
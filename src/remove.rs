@@ -0,0 +1,184 @@
+/// Removing a desktop with a fallback chosen by a strategy
+use crate::backend::DesktopBackend;
+use crate::{Desktop, Error, Result};
+use windows::core::GUID;
+
+/// Desktop that receives the windows of the removed desktop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FallbackStrategy {
+    /// Desktop on the left, or on the right if the removed desktop is the
+    /// first one, this is what Windows does
+    #[default]
+    Left,
+
+    /// Desktop on the right, or on the left if the removed desktop is the
+    /// last one
+    Right,
+
+    /// The current desktop, or if the current desktop is removed, the desktop
+    /// of the topmost window on other desktops. Falls back to `Left` if the
+    /// other desktops have no windows.
+    MostRecentlyUsed,
+
+    /// Desktop by index or GUID
+    Specific(Desktop),
+}
+
+pub(crate) fn remove_desktop_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    desktop: &Desktop,
+    strategy: FallbackStrategy,
+) -> Result<Desktop> {
+    let ids = backend.desktop_ids()?;
+    let id = backend.resolve(desktop)?;
+    if ids.len() < 2 {
+        return Err(Error::CannotRemoveLastDesktop);
+    }
+    let fallback = match strategy {
+        FallbackStrategy::Left => neighbor(&ids, id, false),
+        FallbackStrategy::Right => neighbor(&ids, id, true),
+        FallbackStrategy::MostRecentlyUsed => {
+            let current = backend.current_desktop()?;
+            if current != id {
+                current
+            } else {
+                backend
+                    .recent_desktops()?
+                    .into_iter()
+                    .find(|d| *d != id && ids.contains(d))
+                    .unwrap_or_else(|| neighbor(&ids, id, false))
+            }
+        }
+        FallbackStrategy::Specific(fallback) => {
+            let fallback = backend.resolve(&fallback)?;
+            if fallback == id {
+                return Err(Error::InvalidArgument);
+            }
+            fallback
+        }
+    };
+    backend.remove_desktop(id, fallback)?;
+    Ok(fallback.into())
+}
+
+/// Desktop next to the given one, `ids` must have at least two desktops
+fn neighbor(ids: &[GUID], id: GUID, right: bool) -> GUID {
+    let index = ids.iter().position(|d| *d == id).unwrap_or(0);
+    if (right && index + 1 < ids.len()) || index == 0 {
+        ids[index + 1]
+    } else {
+        ids[index - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+
+    #[test]
+    fn test_remove_neighbors() {
+        let backend = SimulatedBackend::new(4).with_window(20, 2, false);
+        let fallback =
+            remove_desktop_with(&backend, &Desktop::from(1), FallbackStrategy::Left).unwrap();
+        assert_eq!(fallback, Desktop::from(guid(1)));
+        assert_eq!(backend.names(), vec!["D1", "D3", "D4"]);
+        assert_eq!(backend.windows_on(1), vec![20]);
+
+        let fallback =
+            remove_desktop_with(&backend, &Desktop::from(0), FallbackStrategy::Left).unwrap();
+        assert_eq!(fallback, Desktop::from(guid(3)));
+
+        let fallback =
+            remove_desktop_with(&backend, &Desktop::from(0), FallbackStrategy::Right).unwrap();
+        assert_eq!(fallback, Desktop::from(guid(4)));
+        assert_eq!(backend.names(), vec!["D4"]);
+    }
+
+    #[test]
+    fn test_remove_right_of_last() {
+        let backend = SimulatedBackend::new(3);
+        let fallback =
+            remove_desktop_with(&backend, &Desktop::from(2), FallbackStrategy::Right).unwrap();
+        assert_eq!(fallback, Desktop::from(guid(2)));
+    }
+
+    #[test]
+    fn test_remove_most_recently_used() {
+        let backend = SimulatedBackend::new(4)
+            .with_window(10, 1, false)
+            .with_window(40, 4, false)
+            .with_window(30, 3, false);
+
+        // Other than the current desktop falls back to the current one
+        let fallback = remove_desktop_with(
+            &backend,
+            &Desktop::from(1),
+            FallbackStrategy::MostRecentlyUsed,
+        )
+        .unwrap();
+        assert_eq!(fallback, Desktop::from(guid(1)));
+
+        // Current desktop falls back to the desktop of the topmost window
+        let fallback = remove_desktop_with(
+            &backend,
+            &Desktop::from(0),
+            FallbackStrategy::MostRecentlyUsed,
+        )
+        .unwrap();
+        assert_eq!(fallback, Desktop::from(guid(4)));
+        assert_eq!(*backend.current.borrow(), guid(4));
+        assert_eq!(backend.windows_on(4), vec![10, 40]);
+    }
+
+    #[test]
+    fn test_remove_most_recently_used_without_windows() {
+        let backend = SimulatedBackend::new(3);
+        *backend.current.borrow_mut() = guid(2);
+        let fallback = remove_desktop_with(
+            &backend,
+            &Desktop::from(1),
+            FallbackStrategy::MostRecentlyUsed,
+        )
+        .unwrap();
+        assert_eq!(fallback, Desktop::from(guid(1)));
+    }
+
+    #[test]
+    fn test_remove_specific() {
+        let backend = SimulatedBackend::new(3);
+        let fallback = remove_desktop_with(
+            &backend,
+            &Desktop::from(0),
+            FallbackStrategy::Specific(Desktop::from(2)),
+        )
+        .unwrap();
+        assert_eq!(fallback, Desktop::from(guid(3)));
+        assert_eq!(
+            remove_desktop_with(
+                &backend,
+                &Desktop::from(0),
+                FallbackStrategy::Specific(Desktop::from(guid(2)))
+            ),
+            Err(Error::InvalidArgument)
+        );
+        assert_eq!(
+            remove_desktop_with(
+                &backend,
+                &Desktop::from(0),
+                FallbackStrategy::Specific(Desktop::from(7))
+            ),
+            Err(Error::DesktopNotFound)
+        );
+    }
+
+    #[test]
+    fn test_remove_last_desktop() {
+        let backend = SimulatedBackend::new(1);
+        assert_eq!(
+            remove_desktop_with(&backend, &Desktop::from(0), FallbackStrategy::Left),
+            Err(Error::CannotRemoveLastDesktop)
+        );
+        assert_eq!(backend.names(), vec!["D1"]);
+    }
+}
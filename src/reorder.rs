@@ -0,0 +1,232 @@
+/// Sorting and reordering desktops with the fewest moves
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::snapshot::longest_increasing_subsequence;
+use crate::{DesktopState, Error, Result};
+use std::collections::HashMap;
+use windows::core::GUID;
+
+/// Sort the desktops by the key, returns the number of desktops moved
+///
+/// The sort is stable, and only the desktops that are out of place are moved,
+/// so there is one `DesktopMoved` event for each moved desktop. Moving stops
+/// at the first error.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// sort_desktops_by(|desktop| desktop.name.to_lowercase()).unwrap();
+/// ```
+pub fn sort_desktops_by<K, F>(key: F) -> Result<u32>
+where
+    K: Ord,
+    F: Fn(&DesktopState) -> K + Send + 'static,
+{
+    with_com_objects(move |o| sort_desktops_by_with(o, &key))
+}
+
+/// Move the desktops to the given order, returns the number of desktops moved
+///
+/// Desktops missing from the order are placed after the given ones, in their
+/// current order. Moving stops at the first error.
+pub fn reorder_desktops(order: &[GUID]) -> Result<u32> {
+    let order = order.to_vec();
+    with_com_objects(move |o| reorder_desktops_with(o, &order))
+}
+
+pub(crate) fn sort_desktops_by_with<B, K, F>(backend: &B, key: &F) -> Result<u32>
+where
+    B: DesktopBackend + ?Sized,
+    K: Ord,
+    F: Fn(&DesktopState) -> K,
+{
+    let mut desktops = backend.snapshot()?.desktops;
+    desktops.sort_by_key(|d| key(d));
+    let order: Vec<GUID> = desktops.iter().map(|d| d.id).collect();
+    reorder_desktops_with(backend, &order)
+}
+
+pub(crate) fn reorder_desktops_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    order: &[GUID],
+) -> Result<u32> {
+    let current = backend.desktop_ids()?;
+    let mut target = Vec::with_capacity(current.len());
+    for id in order {
+        if !current.contains(id) {
+            return Err(Error::DesktopNotFound);
+        }
+        if target.contains(id) {
+            return Err(Error::InvalidArgument);
+        }
+        target.push(*id);
+    }
+    for id in current.iter() {
+        if !target.contains(id) {
+            target.push(*id);
+        }
+    }
+
+    let moves = plan_moves(&current, &target);
+    for (id, index) in moves.iter() {
+        backend.move_desktop(*id, *index)?;
+    }
+    Ok(moves.len() as u32)
+}
+
+/// Moves that turn the `current` order into the `target` order, both must
+/// have the same desktops
+///
+/// Each move is `(desktop, index)` like `move_desktop`, the desktop is taken
+/// out and inserted at the index. Desktops in the longest subsequence that is
+/// already in the target order stay in place, so the number of moves is the
+/// smallest possible.
+pub(crate) fn plan_moves(current: &[GUID], target: &[GUID]) -> Vec<(GUID, u32)> {
+    let target_indices: HashMap<GUID, usize> =
+        target.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let values: Vec<usize> = current.iter().map(|id| target_indices[id]).collect();
+    let staying: Vec<GUID> = longest_increasing_subsequence(&values)
+        .into_iter()
+        .map(|i| current[i])
+        .collect();
+
+    // Moving desktops are placed right after their predecessor in the target
+    // order, going through the target order keeps the placed ones in order
+    let mut working = current.to_vec();
+    let mut moves = Vec::new();
+    for (i, id) in target.iter().enumerate() {
+        if staying.contains(id) {
+            continue;
+        }
+        let from = working.iter().position(|d| d == id).unwrap_or(0);
+        working.remove(from);
+        let to = match i {
+            0 => 0,
+            _ => {
+                working
+                    .iter()
+                    .position(|d| *d == target[i - 1])
+                    .unwrap_or(0)
+                    + 1
+            }
+        };
+        working.insert(to, *id);
+        if to != from {
+            moves.push((*id, to as u32));
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+
+    fn ids(ns: &[u32]) -> Vec<GUID> {
+        ns.iter().map(|n| guid(*n)).collect()
+    }
+
+    /// Apply the moves like `move_desktop` does
+    fn apply(current: &[GUID], moves: &[(GUID, u32)]) -> Vec<GUID> {
+        let mut result = current.to_vec();
+        for (id, index) in moves {
+            let from = result.iter().position(|d| d == id).unwrap();
+            let id = result.remove(from);
+            result.insert(*index as usize, id);
+        }
+        result
+    }
+
+    #[test]
+    fn test_plan_moves() {
+        let cases: &[(&[u32], &[u32], usize)] = &[
+            (&[], &[], 0),
+            (&[1, 2, 3], &[1, 2, 3], 0),
+            (&[1, 2, 3], &[3, 1, 2], 1),
+            (&[1, 2, 3], &[2, 3, 1], 1),
+            (&[1, 2, 3, 4], &[4, 3, 2, 1], 3),
+            (&[1, 2, 3, 4, 5], &[2, 1, 4, 3, 5], 2),
+            (&[5, 1, 4, 2, 3], &[1, 2, 3, 4, 5], 2),
+        ];
+        for (current, target, count) in cases {
+            let (current, target) = (ids(current), ids(target));
+            let moves = plan_moves(&current, &target);
+            assert_eq!(moves.len(), *count, "{:?}", target);
+            assert_eq!(apply(&current, &moves), target);
+        }
+    }
+
+    #[test]
+    fn test_plan_moves_all_permutations() {
+        let current = ids(&[1, 2, 3, 4, 5]);
+        let mut target = current.clone();
+        let mut permutations = vec![target.clone()];
+        // Heap's algorithm
+        let mut c = [0; 5];
+        let mut i = 0;
+        while i < 5 {
+            if c[i] < i {
+                target.swap(if i % 2 == 0 { 0 } else { c[i] }, i);
+                permutations.push(target.clone());
+                c[i] += 1;
+                i = 0;
+            } else {
+                c[i] = 0;
+                i += 1;
+            }
+        }
+        assert_eq!(permutations.len(), 120);
+        for target in permutations {
+            let moves = plan_moves(&current, &target);
+            let values: Vec<usize> = current
+                .iter()
+                .map(|id| target.iter().position(|t| t == id).unwrap())
+                .collect();
+            assert_eq!(
+                moves.len(),
+                5 - longest_increasing_subsequence(&values).len()
+            );
+            assert_eq!(apply(&current, &moves), target);
+        }
+    }
+
+    #[test]
+    fn test_sort_desktops_by() {
+        let backend = SimulatedBackend::new(4);
+        backend.set_desktop_name(guid(1), "mail").unwrap();
+        backend.set_desktop_name(guid(2), "Code").unwrap();
+        backend.set_desktop_name(guid(3), "build").unwrap();
+        backend.set_desktop_name(guid(4), "Chat").unwrap();
+        let moved =
+            sort_desktops_by_with(&backend, &|d: &DesktopState| d.name.to_lowercase()).unwrap();
+        assert_eq!(backend.names(), vec!["build", "Chat", "Code", "mail"]);
+        assert_eq!(moved, 2);
+
+        let moved =
+            sort_desktops_by_with(&backend, &|d: &DesktopState| d.name.to_lowercase()).unwrap();
+        assert_eq!(moved, 0);
+    }
+
+    #[test]
+    fn test_reorder_desktops() {
+        let backend = SimulatedBackend::new(4);
+        assert_eq!(reorder_desktops_with(&backend, &ids(&[3, 1])).unwrap(), 1);
+        assert_eq!(backend.names(), vec!["D3", "D1", "D2", "D4"]);
+
+        assert_eq!(
+            reorder_desktops_with(&backend, &ids(&[1, 9])),
+            Err(Error::DesktopNotFound)
+        );
+        assert_eq!(
+            reorder_desktops_with(&backend, &ids(&[1, 1])),
+            Err(Error::InvalidArgument)
+        );
+        backend.fail("move_desktop");
+        assert_eq!(
+            reorder_desktops_with(&backend, &ids(&[4])),
+            Err(Error::ServerBusy)
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::error::RETRIED_ERRORS;
+/// Retrying the COM calls after recoverable errors
+///
+/// COM calls fail with transient errors e.g. when explorer.exe is restarted,
+/// in that case the services are dropped and the call is tried again.
+use crate::log::RETRY;
+use crate::metrics::record_retry;
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Delay between the attempts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Retry immediately
+    None,
+
+    /// Wait the same time before each retry
+    Fixed(Duration),
+
+    /// Double the wait before each retry, starting from `initial` up to `max`
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Delay before the retry, `retry` starts from 1
+    pub fn delay(&self, retry: u32) -> Duration {
+        match self {
+            Backoff::None => Duration::ZERO,
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32
+                    .checked_shl(retry.saturating_sub(1))
+                    .unwrap_or(u32::MAX);
+                initial.saturating_mul(factor).min(*max)
+            }
+        }
+    }
+}
+
+/// Policy for retrying COM calls, set it globally with `set_retry_policy` or
+/// for some calls with `with_retry_policy`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    pub max_attempts: u32,
+
+    /// Delay between the attempts
+    pub backoff: Backoff,
+
+    /// Errors that are retried
+    pub transient_errors: Vec<Error>,
+
+    /// Call `CoIncrementMTAUsage` before retrying after `ComNotInitialized`
+    pub increment_mta_usage: bool,
+
+    /// Drop the COM services before retrying, so that they are created again
+    pub drop_services: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            backoff: Backoff::None,
+            transient_errors: RETRIED_ERRORS.to_vec(),
+            increment_mta_usage: true,
+            drop_services: true,
+        }
+    }
+}
+
+/// What to do after a failed attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Return the error to the caller
+    GiveUp,
+
+    /// Try again after the delay
+    Retry {
+        delay: Duration,
+        increment_mta_usage: bool,
+        drop_services: bool,
+    },
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    pub fn never() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Is the error retried by this policy?
+    pub fn is_transient(&self, error: &Error) -> bool {
+        self.transient_errors.iter().any(|e| e == error)
+    }
+
+    /// Decide what to do after the `attempt` failed with `error`, attempts
+    /// start from 1
+    pub fn decide(&self, attempt: u32, error: &Error) -> RetryDecision {
+        if attempt >= self.max_attempts || !self.is_transient(error) {
+            return RetryDecision::GiveUp;
+        }
+        RetryDecision::Retry {
+            delay: self.backoff.delay(attempt),
+            increment_mta_usage: self.increment_mta_usage && error == &Error::ComNotInitialized,
+            drop_services: self.drop_services,
+        }
+    }
+}
+
+static RETRY_POLICY: Mutex<Option<RetryPolicy>> = Mutex::new(None);
+
+thread_local! {
+    static THREAD_RETRY_POLICY: RefCell<Option<RetryPolicy>> = const { RefCell::new(None) };
+}
+
+/// Set the retry policy used by all threads
+pub fn set_retry_policy(policy: RetryPolicy) {
+    if let Ok(mut global) = RETRY_POLICY.lock() {
+        *global = Some(policy);
+    }
+}
+
+/// Get the retry policy in effect for the calls from this thread
+pub fn get_retry_policy() -> RetryPolicy {
+    THREAD_RETRY_POLICY
+        .with(|p| p.borrow().clone())
+        .or_else(|| RETRY_POLICY.lock().ok().and_then(|p| p.clone()))
+        .unwrap_or_default()
+}
+
+/// Run `f` with a different retry policy for the calls made from it
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// // Fail fast, don't retry
+/// let count = with_retry_policy(RetryPolicy::never(), || get_desktop_count());
+/// ```
+pub fn with_retry_policy<R>(policy: RetryPolicy, f: impl FnOnce() -> R) -> R {
+    // Restores the previous policy also if `f` panics
+    struct Restore(Option<RetryPolicy>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            THREAD_RETRY_POLICY.with(|p| *p.borrow_mut() = self.0.take());
+        }
+    }
+
+    let _restore = Restore(THREAD_RETRY_POLICY.with(|p| p.replace(Some(policy))));
+    f()
+}
+
+/// Resets the state between the attempts
+pub(crate) trait RetryBackend {
+    fn increment_mta_usage(&self);
+    fn drop_services(&self);
+}
+
+/// Runs the function, and reruns it according to the policy if it fails
+pub(crate) fn run_with_retry<B, F, R>(
+    operation: &'static str,
+    policy: &RetryPolicy,
+    backend: &B,
+    f: F,
+) -> Result<R>
+where
+    B: RetryBackend + ?Sized,
+    F: Fn() -> Result<R>,
+{
+    let mut attempt = 1;
+    loop {
+        let value = f();
+        let er = match &value {
+            Ok(_) => return value,
+            Err(er) => er,
+        };
+        match policy.decide(attempt, er) {
+            RetryDecision::GiveUp => {
+                ::log::debug!(target: RETRY, "{} failed after {} attempts: {}", operation, attempt, er);
+                return value;
+            }
+            RetryDecision::Retry {
+                delay,
+                increment_mta_usage,
+                drop_services,
+            } => {
+                ::log::warn!(
+                    target: RETRY,
+                    "{} failed with {}, retrying after {:?}",
+                    operation,
+                    er,
+                    delay
+                );
+
+                drop(value);
+                record_retry(operation, drop_services);
+                if increment_mta_usage {
+                    backend.increment_mta_usage();
+                }
+
+                // If private function is decorated, then this drop_services call
+                // will cause borrow issues.
+                if drop_services {
+                    backend.drop_services();
+                }
+                if !delay.is_zero() {
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+
+    /// Backend that returns the scripted results in order
+    #[derive(Default)]
+    struct ScriptedBackend {
+        results: RefCell<VecDeque<Result<u32>>>,
+        calls: Cell<u32>,
+        mta_increments: Cell<u32>,
+        drops: Cell<u32>,
+    }
+
+    impl ScriptedBackend {
+        fn new(results: Vec<Result<u32>>) -> Self {
+            ScriptedBackend {
+                results: RefCell::new(results.into()),
+                ..Default::default()
+            }
+        }
+
+        fn call(&self) -> Result<u32> {
+            self.calls.set(self.calls.get() + 1);
+            self.results
+                .borrow_mut()
+                .pop_front()
+                .expect("Called more than scripted")
+        }
+    }
+
+    impl RetryBackend for ScriptedBackend {
+        fn increment_mta_usage(&self) {
+            self.mta_increments.set(self.mta_increments.get() + 1);
+        }
+
+        fn drop_services(&self) {
+            self.drops.set(self.drops.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let backend = ScriptedBackend::new(vec![
+            Err(Error::RpcServerNotAvailable),
+            Err(Error::ComNotInitialized),
+            Ok(5),
+        ]);
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            run_with_retry("test", &policy, &backend, || backend.call()),
+            Ok(5)
+        );
+        assert_eq!(backend.calls.get(), 3);
+        assert_eq!(backend.drops.get(), 2);
+        assert_eq!(backend.mta_increments.get(), 1);
+    }
+
+    #[test]
+    fn test_retry_gives_up_after_max_attempts() {
+        let backend = ScriptedBackend::new(vec![
+            Err(Error::ClassNotRegistered),
+            Err(Error::ClassNotRegistered),
+            Ok(1),
+        ]);
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            run_with_retry("test", &policy, &backend, || backend.call()),
+            Err(Error::ClassNotRegistered)
+        );
+        assert_eq!(backend.calls.get(), 2);
+    }
+
+    #[test]
+    fn test_retry_not_transient() {
+        let backend = ScriptedBackend::new(vec![Err(Error::DesktopNotFound)]);
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            run_with_retry("test", &policy, &backend, || backend.call()),
+            Err(Error::DesktopNotFound)
+        );
+        assert_eq!(backend.calls.get(), 1);
+        assert_eq!(backend.drops.get(), 0);
+    }
+
+    #[test]
+    fn test_retry_without_resets() {
+        let backend = ScriptedBackend::new(vec![Err(Error::ComNotInitialized), Ok(1)]);
+        let policy = RetryPolicy {
+            increment_mta_usage: false,
+            drop_services: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            run_with_retry("test", &policy, &backend, || backend.call()),
+            Ok(1)
+        );
+        assert_eq!(backend.drops.get(), 0);
+        assert_eq!(backend.mta_increments.get(), 0);
+    }
+
+    #[test]
+    fn test_decide() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            backoff: Backoff::Fixed(Duration::from_millis(50)),
+            transient_errors: vec![Error::RpcServerNotAvailable],
+            increment_mta_usage: true,
+            drop_services: false,
+        };
+        assert_eq!(
+            policy.decide(1, &Error::RpcServerNotAvailable),
+            RetryDecision::Retry {
+                delay: Duration::from_millis(50),
+                increment_mta_usage: false,
+                drop_services: false,
+            }
+        );
+        assert_eq!(
+            policy.decide(3, &Error::RpcServerNotAvailable),
+            RetryDecision::GiveUp
+        );
+        assert_eq!(
+            policy.decide(1, &Error::ComNotInitialized),
+            RetryDecision::GiveUp
+        );
+        assert_eq!(
+            RetryPolicy::never().decide(1, &Error::ComNotInitialized),
+            RetryDecision::GiveUp
+        );
+    }
+
+    #[test]
+    fn test_default_policy_matches_is_transient() {
+        let policy = RetryPolicy::default();
+        for error in policy.transient_errors.iter() {
+            assert!(error.is_transient(), "{:?}", error);
+        }
+        assert!(Error::CallTimeout.is_transient());
+        assert!(!policy.is_transient(&Error::CallTimeout));
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(50),
+        };
+        assert_eq!(backoff.delay(1), Duration::from_millis(10));
+        assert_eq!(backoff.delay(2), Duration::from_millis(20));
+        assert_eq!(backoff.delay(3), Duration::from_millis(40));
+        assert_eq!(backoff.delay(4), Duration::from_millis(50));
+        assert_eq!(backoff.delay(100), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_with_retry_policy() {
+        with_retry_policy(RetryPolicy::never(), || {
+            assert_eq!(get_retry_policy().max_attempts, 1);
+        });
+        assert_ne!(get_retry_policy(), RetryPolicy::never());
+
+        // Restored also when the closure panics
+        let result = std::panic::catch_unwind(|| {
+            with_retry_policy(RetryPolicy::never(), || panic!("Failing closure"))
+        });
+        assert!(result.is_err());
+        assert_ne!(get_retry_policy(), RetryPolicy::never());
+    }
+}
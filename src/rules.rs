@@ -0,0 +1,538 @@
+/// Rules that place the windows on desktops by their application, title or
+/// class
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::log::RULES;
+use crate::{
+    listen_desktop_events, Desktop, DesktopEvent, DesktopEventThread, Error, FailedStep, Result,
+    Window, WindowInfo,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use windows::core::GUID;
+use windows::Win32::Foundation::HWND;
+use windows::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+
+/// Field of the window that a matcher is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowField {
+    /// Application user model ID
+    AppId,
+    Title,
+    ClassName,
+
+    /// Path of the executable
+    Exe,
+}
+
+/// Pattern for a window field
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Matcher {
+    /// Case insensitive glob, `*` matches any text and `?` any character
+    Glob(String),
+
+    /// Regular expression, the field matches if the expression is found in it
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Matcher {
+    pub fn glob(pattern: &str) -> Matcher {
+        Matcher::Glob(pattern.to_owned())
+    }
+
+    /// Regular expression, gives `Error::InvalidArgument` if it is not valid
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Matcher> {
+        regex::Regex::new(pattern)
+            .map(Matcher::Regex)
+            .map_err(|_| Error::InvalidArgument)
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Glob(pattern) => glob_match(pattern, text),
+            #[cfg(feature = "regex")]
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Desktop of a rule action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleDesktop {
+    /// The first desktop with the name
+    Named(String),
+    Desktop(Desktop),
+}
+
+/// Action of a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleAction {
+    MoveTo(RuleDesktop),
+
+    /// Pin the window to all desktops
+    Pin,
+
+    /// Pin the application of the window to all desktops
+    PinApp,
+
+    /// Switch to the desktop of the window, after it's moved
+    Switch,
+}
+
+/// Rule with the conditions and the actions, the conditions must all match
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let rules = RuleSet::new(vec![
+///     Rule::new("chat")
+///         .when(WindowField::AppId, Matcher::glob("*Slack*"))
+///         .then(RuleAction::MoveTo(RuleDesktop::Named("Chat".to_owned()))),
+///     Rule::new("music")
+///         .when(WindowField::Exe, Matcher::glob("*\\Spotify.exe"))
+///         .then(RuleAction::Pin),
+/// ]);
+/// let _engine = rules.start().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+
+    /// Rules with higher priority are evaluated first
+    pub priority: i32,
+    pub conditions: Vec<(WindowField, Matcher)>,
+    pub actions: Vec<RuleAction>,
+}
+
+impl Rule {
+    pub fn new(name: &str) -> Rule {
+        Rule {
+            name: name.to_owned(),
+            priority: 0,
+            conditions: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn priority(mut self, priority: i32) -> Rule {
+        self.priority = priority;
+        self
+    }
+
+    pub fn when(mut self, field: WindowField, matcher: Matcher) -> Rule {
+        self.conditions.push((field, matcher));
+        self
+    }
+
+    pub fn then(mut self, action: RuleAction) -> Rule {
+        self.actions.push(action);
+        self
+    }
+
+    /// Do all conditions match the window? Missing fields do not match.
+    pub fn matches(&self, info: &WindowInfo) -> bool {
+        self.conditions.iter().all(|(field, matcher)| {
+            let value = match field {
+                WindowField::AppId => info.app_id.as_deref(),
+                WindowField::Title => Some(info.title.as_str()),
+                WindowField::ClassName => Some(info.class_name.as_str()),
+                WindowField::Exe => info.exe.as_deref(),
+            };
+            value.is_some_and(|value| matcher.is_match(value))
+        })
+    }
+}
+
+/// Result of `RuleSet::sweep`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RuleReport {
+    /// Windows and the names of the rules applied to them
+    pub applied: Vec<(Window, String)>,
+    pub failures: Vec<FailedStep>,
+}
+
+impl RuleReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Rules in the priority order, only the first matching rule is applied to
+/// a window
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>) -> RuleSet {
+        let mut set = RuleSet::default();
+        for rule in rules {
+            set.add(rule);
+        }
+        set
+    }
+
+    /// Add the rule after the rules with the same or higher priority
+    pub fn add(&mut self, rule: Rule) {
+        let index = self.rules.partition_point(|r| r.priority >= rule.priority);
+        self.rules.insert(index, rule);
+    }
+
+    /// Rules in the evaluation order
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The first rule that matches the window
+    pub fn matching_rule(&self, info: &WindowInfo) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(info))
+    }
+
+    /// Apply the first matching rule to the window, returns the name of the
+    /// applied rule
+    pub fn apply_to(&self, window: Window) -> Result<Option<String>> {
+        let info = window.info()?;
+        let Some(rule) = self.matching_rule(&info) else {
+            return Ok(None);
+        };
+        let actions = rule.actions.clone();
+        with_com_objects(move |o| apply_actions_with(o, &actions, &info))?;
+        ::log::debug!(target: RULES, "Applied rule {} to {:?}", rule.name, window.0);
+        Ok(Some(rule.name.clone()))
+    }
+
+    /// Apply the rules to all windows shown in the task switcher
+    pub fn sweep(&self) -> Result<RuleReport> {
+        let windows = with_com_objects(|o| o.get_all_windows())?;
+        let mut report = RuleReport::default();
+        for (hwnd, _) in windows {
+            match self.apply_to(Window(hwnd)) {
+                Ok(Some(rule)) => report.applied.push((Window(hwnd), rule)),
+                Ok(None) => {}
+                Err(er) => report.failures.push(FailedStep::new(
+                    format!("apply rules to window 0x{:X}", hwnd.0),
+                    er,
+                )),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sweep the windows, and apply the rules to windows on
+    /// `DesktopEvent::WindowChanged`
+    ///
+    /// The rules are applied once for each window, so that the windows can
+    /// be moved elsewhere afterwards. The rules stop when the returned
+    /// `RuleEngine` is dropped.
+    pub fn start(self) -> Result<RuleEngine> {
+        let report = self.sweep()?;
+        for failure in report.failures.iter() {
+            ::log::warn!(target: RULES, "{} failed: {}", failure.step, failure.error);
+        }
+        let mut handled: HashMap<isize, WindowIdentity> = report
+            .applied
+            .iter()
+            .filter_map(|(w, _)| window_identity(w.0))
+            .map(|identity| (identity.0, identity))
+            .collect();
+        let rules = Arc::new(self);
+        let (tx, rx) = std::sync::mpsc::channel::<DesktopEvent>();
+        let events = listen_desktop_events(tx)?;
+        let thread = std::thread::spawn(move || {
+            for event in rx {
+                let DesktopEvent::WindowChanged(hwnd) = event else {
+                    continue;
+                };
+                // A handle reused by a new window has a different identity,
+                // so the new window gets the rules
+                let Some(identity) = window_identity(hwnd) else {
+                    handled.remove(&hwnd.0);
+                    continue;
+                };
+                if handled.get(&hwnd.0) == Some(&identity) {
+                    continue;
+                }
+                match rules.apply_to(Window(hwnd)) {
+                    Ok(Some(_)) => {
+                        handled.insert(hwnd.0, identity);
+                    }
+                    Ok(None) => {}
+                    Err(er) if er.is_not_found() => {}
+                    Err(er) => ::log::warn!(
+                        target: RULES,
+                        "Applying rules to {:?} failed: {}",
+                        hwnd,
+                        er
+                    ),
+                }
+            }
+        });
+        Ok(RuleEngine {
+            events: Some(events),
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Rules applied to the windows in the background, create with
+/// `RuleSet::start`, the rules stop when the value is dropped
+#[derive(Debug)]
+pub struct RuleEngine {
+    events: Option<DesktopEventThread>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl RuleEngine {
+    /// Stop the listener and join the thread, normally you don't need to
+    /// call this as drop calls this automatically
+    pub fn stop(&mut self) -> std::thread::Result<()> {
+        // Stopping the listener drops the sender, which ends the rule thread
+        if let Some(mut events) = self.events.take() {
+            events.stop()?;
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RuleEngine {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            ::log::error!(target: RULES, "Could not stop rule thread {:?}", err);
+        }
+    }
+}
+
+/// Window handle with the process and thread IDs of the window, handles are
+/// reused after the windows are closed
+type WindowIdentity = (isize, u32, u32);
+
+fn window_identity(hwnd: HWND) -> Option<WindowIdentity> {
+    let mut pid = 0;
+    let thread = unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+    if thread == 0 {
+        return None;
+    }
+    Some((hwnd.0, pid, thread))
+}
+
+pub(crate) fn apply_actions_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    actions: &[RuleAction],
+    info: &WindowInfo,
+) -> Result<()> {
+    let hwnd = info.window.hwnd();
+    let mut desktop: Option<GUID> = None;
+    for action in actions {
+        match action {
+            RuleAction::MoveTo(target) => {
+                let id = resolve_rule_desktop(backend, target)?;
+                backend.move_window(hwnd, id)?;
+                desktop = Some(id);
+            }
+            RuleAction::Pin => backend.pin_window(hwnd)?,
+            RuleAction::PinApp => {
+                let app_id = info.app_id.as_deref().ok_or(Error::InvalidArgument)?;
+                backend.pin_app(app_id)?;
+            }
+            RuleAction::Switch => {
+                let id = match (desktop, info.desktop) {
+                    (Some(id), _) => id,
+                    (None, Some(current)) => backend.resolve(&current)?,
+                    (None, None) => return Err(Error::WindowNotFound),
+                };
+                backend.switch_desktop(id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn resolve_rule_desktop<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    target: &RuleDesktop,
+) -> Result<GUID> {
+    match target {
+        RuleDesktop::Desktop(desktop) => backend.resolve(desktop),
+        RuleDesktop::Named(name) => {
+            for id in backend.desktop_ids()? {
+                if backend.desktop_name(id)? == *name {
+                    return Ok(id);
+                }
+            }
+            Err(Error::DesktopNotFound)
+        }
+    }
+}
+
+/// Case insensitive glob match of the whole text, `*` matches any text and
+/// `?` any single character
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+
+    // Backtrack to the last star when the rest does not match
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+
+    fn info(hwnd: isize, app_id: Option<&str>, title: &str, exe: &str) -> WindowInfo {
+        WindowInfo {
+            window: Window(HWND(hwnd)),
+            title: title.to_owned(),
+            class_name: "Chrome_WidgetWin_1".to_owned(),
+            app_id: app_id.map(|a| a.to_owned()),
+            exe: Some(exe.to_owned()),
+            desktop: Some(Desktop::from(guid(1))),
+            pinned: false,
+        }
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*slack*", "com.squirrel.Slack.slack"));
+        assert!(glob_match("*.exe", "C:\\Spotify.EXE"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+        assert!(!glob_match("a*b*c", "aXXbYYb"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("slack", "slack2"));
+        assert!(glob_match("ÄÖ*", "äöå"));
+    }
+
+    #[test]
+    fn test_rule_priority() {
+        let rules = RuleSet::new(vec![
+            Rule::new("browser")
+                .when(WindowField::ClassName, Matcher::glob("Chrome_*"))
+                .then(RuleAction::MoveTo(RuleDesktop::Named("Web".to_owned()))),
+            Rule::new("chat")
+                .priority(10)
+                .when(WindowField::AppId, Matcher::glob("*Slack*"))
+                .then(RuleAction::MoveTo(RuleDesktop::Named("Chat".to_owned()))),
+            Rule::new("other browser").when(WindowField::Title, Matcher::glob("*")),
+        ]);
+        let names: Vec<&str> = rules.rules().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["chat", "browser", "other browser"]);
+
+        let slack = info(
+            1,
+            Some("com.squirrel.Slack.slack"),
+            "Slack",
+            "C:\\slack.exe",
+        );
+        assert_eq!(rules.matching_rule(&slack).unwrap().name, "chat");
+        let chrome = info(2, None, "New tab", "C:\\chrome.exe");
+        assert_eq!(rules.matching_rule(&chrome).unwrap().name, "browser");
+    }
+
+    #[test]
+    fn test_rule_conditions() {
+        let rule = Rule::new("spotify")
+            .when(WindowField::Exe, Matcher::glob("*\\spotify.exe"))
+            .when(WindowField::Title, Matcher::glob("Spotify*"));
+        assert!(rule.matches(&info(1, None, "Spotify Premium", "C:\\Spotify.exe")));
+        assert!(!rule.matches(&info(1, None, "Settings", "C:\\Spotify.exe")));
+
+        // Missing application ID does not match even a star
+        let rule = Rule::new("any app").when(WindowField::AppId, Matcher::glob("*"));
+        assert!(!rule.matches(&info(1, None, "", "")));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex_matcher() {
+        let rule = Rule::new("issues").when(
+            WindowField::Title,
+            Matcher::regex(r"^#\d+ .* - GitHub").unwrap(),
+        );
+        assert!(rule.matches(&info(1, None, "#123 Fix it - GitHub", "")));
+        assert!(!rule.matches(&info(1, None, "GitHub", "")));
+        assert_eq!(Matcher::regex("(").unwrap_err(), Error::InvalidArgument);
+    }
+
+    #[test]
+    fn test_apply_actions() {
+        let backend = SimulatedBackend::new(3).with_window(10, 1, false);
+        backend.set_desktop_name(guid(3), "Chat").unwrap();
+        let slack = info(10, Some("Slack"), "Slack", "C:\\slack.exe");
+        let actions = [
+            RuleAction::MoveTo(RuleDesktop::Named("Chat".to_owned())),
+            RuleAction::Switch,
+            RuleAction::PinApp,
+        ];
+        apply_actions_with(&backend, &actions, &slack).unwrap();
+        assert_eq!(backend.windows_on(3), vec![10]);
+        assert_eq!(*backend.current.borrow(), guid(3));
+        assert_eq!(*backend.pinned_apps.borrow(), vec!["Slack"]);
+
+        apply_actions_with(&backend, &[RuleAction::Pin], &slack).unwrap();
+        assert!(backend.is_window_pinned(HWND(10)).unwrap());
+    }
+
+    #[test]
+    fn test_apply_actions_errors() {
+        let backend = SimulatedBackend::new(2).with_window(10, 2, false);
+        let window = info(10, None, "", "");
+        assert_eq!(
+            apply_actions_with(
+                &backend,
+                &[RuleAction::MoveTo(RuleDesktop::Named("Chat".to_owned()))],
+                &window
+            ),
+            Err(Error::DesktopNotFound)
+        );
+        assert_eq!(
+            apply_actions_with(&backend, &[RuleAction::PinApp], &window),
+            Err(Error::InvalidArgument)
+        );
+
+        // Switching without a move goes to the desktop of the window
+        let window = WindowInfo {
+            desktop: Some(Desktop::from(guid(2))),
+            ..window
+        };
+        assert_eq!(*backend.current.borrow(), guid(1));
+        apply_actions_with(&backend, &[RuleAction::Switch], &window).unwrap();
+        assert_eq!(*backend.current.borrow(), guid(2));
+
+        let window = WindowInfo {
+            desktop: None,
+            ..window
+        };
+        assert_eq!(
+            apply_actions_with(&backend, &[RuleAction::Switch], &window),
+            Err(Error::WindowNotFound)
+        );
+    }
+}
@@ -0,0 +1,495 @@
+/// Desktop state snapshots, and diffing them into `DesktopEvent`s
+///
+/// Snapshots are used by the polling listener, which is an alternative to the
+/// COM notifications in case `IVirtualDesktopNotificationService::register`
+/// does not work on the running Windows build.
+use crate::comobjects::{with_com_objects, DesktopInternal};
+use crate::reorder::plan_moves;
+use crate::{Desktop, DesktopEvent, Result};
+use std::collections::HashMap;
+use windows::core::GUID;
+
+/// State of a single desktop at the time of the snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopState {
+    pub id: GUID,
+    pub name: String,
+    pub wallpaper: String,
+}
+
+/// State of all desktops at the time of the snapshot, desktops are in the
+/// index order.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DesktopSnapshot {
+    pub desktops: Vec<DesktopState>,
+    pub current: Option<GUID>,
+}
+
+impl DesktopSnapshot {
+    /// Index of the desktop in this snapshot
+    pub fn index_of(&self, id: &GUID) -> Option<usize> {
+        self.desktops.iter().position(|d| d.id == *id)
+    }
+
+    /// Events required to go from this snapshot to the `new` snapshot, see
+    /// `diff_snapshots`
+    pub fn diff(&self, new: &DesktopSnapshot) -> Vec<DesktopEvent> {
+        diff_snapshots(self, new)
+    }
+
+    /// Update the snapshot with the event, so that it stays in step with the
+    /// events already sent
+    ///
+    /// Events of desktops given only by the index are ignored, the listener
+    /// gives all desktops by the GUID. Created desktops are placed last with
+    /// the wallpaper of the current desktop, as Windows creates them.
+    pub(crate) fn apply_event(&mut self, event: &DesktopEvent) {
+        let guid_of = |desktop: &Desktop| match DesktopInternal::from(*desktop) {
+            DesktopInternal::Guid(id) | DesktopInternal::IndexGuid(_, id) => Some(id),
+            DesktopInternal::Index(_) => None,
+        };
+        match event {
+            DesktopEvent::DesktopCreated(desktop) => {
+                if let Some(id) = guid_of(desktop) {
+                    if self.index_of(&id).is_none() {
+                        let wallpaper = self
+                            .current
+                            .and_then(|current| self.index_of(&current))
+                            .map(|i| self.desktops[i].wallpaper.clone())
+                            .unwrap_or_default();
+                        self.desktops.push(DesktopState {
+                            id,
+                            name: String::new(),
+                            wallpaper,
+                        });
+                    }
+                }
+            }
+            DesktopEvent::DesktopDestroyed {
+                destroyed,
+                fallback,
+            } => {
+                if let Some(id) = guid_of(destroyed) {
+                    self.desktops.retain(|d| d.id != id);
+                    if self.current == Some(id) {
+                        self.current = guid_of(fallback);
+                    }
+                }
+            }
+            DesktopEvent::DesktopChanged { new, .. } => {
+                if let Some(id) = guid_of(new) {
+                    self.current = Some(id);
+                }
+            }
+            DesktopEvent::DesktopNameChanged(desktop, name) => {
+                let index = guid_of(desktop).and_then(|id| self.index_of(&id));
+                if let Some(index) = index {
+                    self.desktops[index].name = name.clone();
+                }
+            }
+            DesktopEvent::DesktopWallpaperChanged(desktop, wallpaper) => {
+                let index = guid_of(desktop).and_then(|id| self.index_of(&id));
+                if let Some(index) = index {
+                    self.desktops[index].wallpaper = wallpaper.clone();
+                }
+            }
+            DesktopEvent::DesktopMoved {
+                desktop, new_index, ..
+            } => {
+                let index = guid_of(desktop).and_then(|id| self.index_of(&id));
+                if let Some(index) = index {
+                    let state = self.desktops.remove(index);
+                    let new_index = (*new_index).clamp(0, self.desktops.len() as i64);
+                    self.desktops.insert(new_index as usize, state);
+                }
+            }
+            DesktopEvent::WindowChanged(_) => {}
+        }
+    }
+}
+
+/// Get the snapshot of all desktops
+pub fn get_desktop_snapshot() -> Result<DesktopSnapshot> {
+    with_com_objects(|o| o.get_snapshot())
+}
+
+/// Synthesize events that describe the changes from `old` to `new` snapshot
+///
+/// Events are given in this order: created, destroyed, moved, name changed,
+/// wallpaper changed and lastly the current desktop changed. Created desktops
+/// get the name and the wallpaper events only if they have them.
+///
+/// Only desktops that changed their position relative to others get a
+/// `DesktopMoved` event, e.g. removing the first desktop does not make all
+/// other desktops moved.
+pub fn diff_snapshots(old: &DesktopSnapshot, new: &DesktopSnapshot) -> Vec<DesktopEvent> {
+    let mut events = Vec::new();
+    let old_indices: HashMap<GUID, usize> = old
+        .desktops
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (d.id, i))
+        .collect();
+    let new_indices: HashMap<GUID, usize> = new
+        .desktops
+        .iter()
+        .enumerate()
+        .map(|(i, d)| (d.id, i))
+        .collect();
+
+    // Created
+    for desktop in new.desktops.iter() {
+        if !old_indices.contains_key(&desktop.id) {
+            events.push(DesktopEvent::DesktopCreated(desktop.id.into()));
+        }
+    }
+
+    // Destroyed, the fallback is not known, so it's assumed to be the current
+    // desktop after the change
+    let fallback = new.current.or_else(|| new.desktops.first().map(|d| d.id));
+    if let Some(fallback) = fallback {
+        for desktop in old.desktops.iter() {
+            if !new_indices.contains_key(&desktop.id) {
+                events.push(DesktopEvent::DesktopDestroyed {
+                    destroyed: desktop.id.into(),
+                    fallback: fallback.into(),
+                });
+            }
+        }
+    }
+
+    // Moved, after the creations and removals the surviving desktops are in
+    // the old order and the created desktops last. The moves are planned
+    // from that order, so applying them in order gives the new order.
+    let intermediate: Vec<GUID> = old
+        .desktops
+        .iter()
+        .filter(|d| new_indices.contains_key(&d.id))
+        .chain(
+            new.desktops
+                .iter()
+                .filter(|d| !old_indices.contains_key(&d.id)),
+        )
+        .map(|d| d.id)
+        .collect();
+    let target: Vec<GUID> = new.desktops.iter().map(|d| d.id).collect();
+    for (id, index) in plan_moves(&intermediate, &target) {
+        let old_index = old_indices
+            .get(&id)
+            .copied()
+            .or_else(|| intermediate.iter().position(|d| *d == id))
+            .unwrap_or(0);
+        events.push(DesktopEvent::DesktopMoved {
+            desktop: id.into(),
+            old_index: old_index as i64,
+            new_index: index as i64,
+        });
+    }
+
+    // Names, new desktops get the name only if they have one
+    for desktop in new.desktops.iter() {
+        let old_name = old_indices
+            .get(&desktop.id)
+            .map(|i| old.desktops[*i].name.as_str())
+            .unwrap_or("");
+        if desktop.name != old_name {
+            events.push(DesktopEvent::DesktopNameChanged(
+                desktop.id.into(),
+                desktop.name.clone(),
+            ));
+        }
+    }
+
+    // Wallpapers, new desktops get the wallpaper only if they have one
+    for desktop in new.desktops.iter() {
+        let old_wallpaper = old_indices
+            .get(&desktop.id)
+            .map(|i| old.desktops[*i].wallpaper.as_str())
+            .unwrap_or("");
+        if desktop.wallpaper != old_wallpaper {
+            events.push(DesktopEvent::DesktopWallpaperChanged(
+                desktop.id.into(),
+                desktop.wallpaper.clone(),
+            ));
+        }
+    }
+
+    // Current desktop
+    if let (Some(old_current), Some(new_current)) = (old.current, new.current) {
+        if old_current != new_current {
+            events.push(DesktopEvent::DesktopChanged {
+                new: Desktop::from(new_current),
+                old: Desktop::from(old_current),
+            });
+        }
+    }
+
+    events
+}
+
+/// Positions of the longest strictly increasing subsequence in `values`
+pub(crate) fn longest_increasing_subsequence(values: &[usize]) -> Vec<usize> {
+    // Patience sorting, `tails[k]` is the position of the smallest tail of all
+    // increasing subsequences of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (i, value) in values.iter().enumerate() {
+        let k = tails.partition_point(|&t| values[t] < *value);
+        if k > 0 {
+            previous[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+
+    let mut result = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        result.push(i);
+        next = previous[i];
+    }
+    result.reverse();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guid(n: u32) -> GUID {
+        GUID::from_values(n, 0, 0, [0; 8])
+    }
+
+    fn state(n: u32, name: &str) -> DesktopState {
+        DesktopState {
+            id: guid(n),
+            name: name.to_owned(),
+            wallpaper: String::new(),
+        }
+    }
+
+    fn snapshot(desktops: Vec<DesktopState>, current: u32) -> DesktopSnapshot {
+        DesktopSnapshot {
+            desktops,
+            current: Some(guid(current)),
+        }
+    }
+
+    #[test]
+    fn test_lis() {
+        assert_eq!(longest_increasing_subsequence(&[]), Vec::<usize>::new());
+        assert_eq!(longest_increasing_subsequence(&[0, 1, 2]), vec![0, 1, 2]);
+        assert_eq!(longest_increasing_subsequence(&[1, 2, 0]), vec![0, 1]);
+        assert_eq!(longest_increasing_subsequence(&[2, 0, 1]), vec![1, 2]);
+        assert_eq!(
+            longest_increasing_subsequence(&[3, 0, 4, 1, 2]).len(),
+            3,
+            "0, 1, 2 is the longest"
+        );
+    }
+
+    #[test]
+    fn test_diff_no_changes() {
+        let a = snapshot(vec![state(1, "A"), state(2, "B")], 1);
+        assert_eq!(diff_snapshots(&a, &a.clone()), vec![]);
+    }
+
+    #[test]
+    fn test_diff_created_and_destroyed() {
+        let old = snapshot(vec![state(1, "A"), state(2, "B")], 2);
+        let new = snapshot(vec![state(1, "A"), state(3, "")], 1);
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![
+                DesktopEvent::DesktopCreated(guid(3).into()),
+                DesktopEvent::DesktopDestroyed {
+                    destroyed: guid(2).into(),
+                    fallback: guid(1).into(),
+                },
+                DesktopEvent::DesktopChanged {
+                    new: guid(1).into(),
+                    old: guid(2).into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_created_with_name() {
+        let old = snapshot(vec![state(1, "A")], 1);
+        let new = snapshot(vec![state(1, "A"), state(2, "B")], 1);
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![
+                DesktopEvent::DesktopCreated(guid(2).into()),
+                DesktopEvent::DesktopNameChanged(guid(2).into(), "B".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_removing_first_is_not_a_move() {
+        let old = snapshot(vec![state(1, ""), state(2, ""), state(3, "")], 2);
+        let new = snapshot(vec![state(2, ""), state(3, "")], 2);
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![DesktopEvent::DesktopDestroyed {
+                destroyed: guid(1).into(),
+                fallback: guid(2).into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_single_move() {
+        let old = snapshot(vec![state(1, ""), state(2, ""), state(3, "")], 1);
+        let new = snapshot(vec![state(2, ""), state(3, ""), state(1, "")], 1);
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![DesktopEvent::DesktopMoved {
+                desktop: guid(1).into(),
+                old_index: 0,
+                new_index: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_name_and_wallpaper() {
+        let old = snapshot(vec![state(1, "A")], 1);
+        let mut new = snapshot(vec![state(1, "B")], 1);
+        new.desktops[0].wallpaper = "C:\\wall.jpg".to_owned();
+        assert_eq!(
+            diff_snapshots(&old, &new),
+            vec![
+                DesktopEvent::DesktopNameChanged(guid(1).into(), "B".to_owned()),
+                DesktopEvent::DesktopWallpaperChanged(guid(1).into(), "C:\\wall.jpg".to_owned()),
+            ]
+        );
+    }
+
+    /// Apply the events to the snapshot, like the listener does
+    fn replay(old: &DesktopSnapshot, events: &[DesktopEvent]) -> DesktopSnapshot {
+        let mut snapshot = old.clone();
+        for event in events {
+            snapshot.apply_event(event);
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_diff_replays_to_new() {
+        let mut old = snapshot(vec![state(1, "A"), state(2, "B"), state(3, "")], 1);
+        old.desktops[0].wallpaper = "C:\\a.jpg".to_owned();
+        old.desktops[1].wallpaper = "C:\\b.jpg".to_owned();
+        let mut new = snapshot(
+            vec![state(4, "New"), state(3, ""), state(1, "A"), state(5, "")],
+            1,
+        );
+        new.desktops[0].wallpaper = "C:\\new.jpg".to_owned();
+        new.desktops[1].wallpaper = "C:\\c.jpg".to_owned();
+        new.desktops[2].wallpaper = "C:\\a.jpg".to_owned();
+        new.desktops[3].wallpaper = "C:\\a.jpg".to_owned();
+        let events = diff_snapshots(&old, &new);
+        assert!(events.contains(&DesktopEvent::DesktopWallpaperChanged(
+            guid(4).into(),
+            "C:\\new.jpg".to_owned()
+        )));
+        assert_eq!(replay(&old, &events), new);
+
+        // All orders of the survivors and the created desktops
+        let ids = [1, 3, 4, 5];
+        for a in 0..4 {
+            for b in 0..4 {
+                for c in 0..4 {
+                    for d in 0..4 {
+                        let order = [ids[a], ids[b], ids[c], ids[d]];
+                        let mut sorted = order;
+                        sorted.sort();
+                        if sorted != ids {
+                            continue;
+                        }
+                        let new = snapshot(order.iter().map(|n| state(*n, "")).collect(), 1);
+                        let old = snapshot(vec![state(1, ""), state(2, ""), state(3, "")], 1);
+                        let events = diff_snapshots(&old, &new);
+                        assert_eq!(replay(&old, &events), new, "{:?}", order);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_resync_after_live_create() {
+        let mut before = snapshot(vec![state(1, "A")], 1);
+        before.desktops[0].wallpaper = "C:\\a.jpg".to_owned();
+        let mut tracked = before.clone();
+        tracked.apply_event(&DesktopEvent::DesktopCreated(guid(2).into()));
+
+        // Windows gives the new desktop the wallpaper of the current desktop
+        let mut after = before.clone();
+        after.desktops.push(DesktopState {
+            id: guid(2),
+            name: String::new(),
+            wallpaper: "C:\\a.jpg".to_owned(),
+        });
+        assert_eq!(tracked.diff(&after), vec![]);
+    }
+
+    #[test]
+    fn test_resync_sends_each_event_once() {
+        // Listener forwards the rename and the move, and then disconnects
+        let before = snapshot(vec![state(1, "A"), state(2, "B"), state(3, "C")], 1);
+        let live = [
+            DesktopEvent::DesktopNameChanged(guid(1).into(), "X".to_owned()),
+            DesktopEvent::DesktopMoved {
+                desktop: guid(3).into(),
+                old_index: 2,
+                new_index: 0,
+            },
+        ];
+        let mut tracked = before.clone();
+        for event in live.iter() {
+            tracked.apply_event(event);
+        }
+
+        // While disconnected desktop 2 is destroyed and desktop 4 is created
+        let after = snapshot(vec![state(3, "C"), state(1, "X"), state(4, "")], 1);
+        let resync = tracked.diff(&after);
+        assert_eq!(
+            resync,
+            vec![
+                DesktopEvent::DesktopCreated(guid(4).into()),
+                DesktopEvent::DesktopDestroyed {
+                    destroyed: guid(2).into(),
+                    fallback: guid(1).into(),
+                },
+            ]
+        );
+
+        // Live and resynchronized events together bring `before` to `after`
+        let mut replayed = before;
+        for event in live.iter().chain(resync.iter()) {
+            replayed.apply_event(event);
+        }
+        assert_eq!(replayed, after);
+    }
+
+    #[test]
+    fn test_apply_event_current() {
+        let mut tracked = snapshot(vec![state(1, ""), state(2, "")], 2);
+        tracked.apply_event(&DesktopEvent::DesktopChanged {
+            new: guid(1).into(),
+            old: guid(2).into(),
+        });
+        assert_eq!(tracked.current, Some(guid(1)));
+        tracked.apply_event(&DesktopEvent::DesktopDestroyed {
+            destroyed: guid(1).into(),
+            fallback: guid(2).into(),
+        });
+        assert_eq!(tracked, snapshot(vec![state(2, "")], 2));
+    }
+}
@@ -0,0 +1,498 @@
+/// Declarative desktop configuration, and planning the operations that
+/// reconcile the desktops with it
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::remove::remove_desktop_with;
+use crate::reorder::plan_moves;
+use crate::{create_desktop_with, Desktop, DesktopSnapshot, FallbackStrategy, Result};
+use std::fmt;
+use windows::core::GUID;
+
+/// Desktops that should exist, in the index order
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let spec = DesktopSpec {
+///     desktops: vec![
+///         SpecDesktop::named("Mail"),
+///         SpecDesktop::named("Code"),
+///         SpecDesktop::named("Build").with_wallpaper("C:\\wallpapers\\build.jpg"),
+///     ],
+///     remove_extra: false,
+/// };
+/// for operation in spec.dry_run().unwrap() {
+///     println!("{}", operation);
+/// }
+/// spec.apply().unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DesktopSpec {
+    pub desktops: Vec<SpecDesktop>,
+
+    /// Remove the desktops that do not match any in the spec, their windows
+    /// are moved to the first desktop. Otherwise they are kept after the
+    /// desktops of the spec. With an empty spec the first desktop is kept, as
+    /// the last desktop can't be removed.
+    pub remove_extra: bool,
+}
+
+/// Desktop of a `DesktopSpec`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SpecDesktop {
+    /// Desktop is matched by the GUID first, and then by the name
+    pub id: Option<GUID>,
+    pub name: String,
+
+    /// Wallpaper path, `None` leaves the wallpaper as is
+    pub wallpaper: Option<String>,
+}
+
+impl SpecDesktop {
+    /// Desktop matched by the name
+    pub fn named(name: &str) -> SpecDesktop {
+        SpecDesktop {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_id(mut self, id: GUID) -> SpecDesktop {
+        self.id = Some(id);
+        self
+    }
+
+    pub fn with_wallpaper(mut self, path: &str) -> SpecDesktop {
+        self.wallpaper = Some(path.to_owned());
+        self
+    }
+}
+
+/// Operation of a plan, in the order of the plan: renames and wallpapers,
+/// moves, creations and lastly removals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    Rename {
+        desktop: GUID,
+        name: String,
+    },
+    SetWallpaper {
+        desktop: GUID,
+        wallpaper: String,
+    },
+    Move {
+        desktop: GUID,
+        index: u32,
+    },
+    Create {
+        index: u32,
+        name: String,
+        wallpaper: Option<String>,
+    },
+
+    /// Windows of the removed desktop are moved to the fallback, which is the
+    /// first desktop of the spec
+    Remove {
+        desktop: GUID,
+        fallback: Desktop,
+    },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Rename { desktop, name } => {
+                write!(f, "rename desktop {:?} to \"{}\"", desktop, name)
+            }
+            Operation::SetWallpaper { desktop, wallpaper } => {
+                write!(f, "set wallpaper of desktop {:?} to {}", desktop, wallpaper)
+            }
+            Operation::Move { desktop, index } => {
+                write!(f, "move desktop {:?} to {}", desktop, index)
+            }
+            Operation::Create {
+                index,
+                name,
+                wallpaper,
+            } => {
+                write!(f, "create desktop \"{}\" at {}", name, index)?;
+                if let Some(wallpaper) = wallpaper {
+                    write!(f, " with wallpaper {}", wallpaper)?;
+                }
+                Ok(())
+            }
+            Operation::Remove { desktop, fallback } => {
+                write!(
+                    f,
+                    "remove desktop {:?} falling back to {}",
+                    desktop, fallback
+                )
+            }
+        }
+    }
+}
+
+impl DesktopSpec {
+    /// Operations that turn the desktops of the snapshot into this spec,
+    /// see `plan`
+    pub fn plan(&self, snapshot: &DesktopSnapshot) -> Vec<Operation> {
+        plan(self, snapshot)
+    }
+
+    /// Plan against the current desktops and apply the plan, returns the
+    /// applied operations
+    pub fn apply(&self) -> Result<Vec<Operation>> {
+        let spec = self.clone();
+        with_com_objects(move |o| {
+            let operations = plan(&spec, &o.snapshot()?);
+            apply_plan_with(o, &operations)?;
+            Ok(operations)
+        })
+    }
+
+    /// Plan against the current desktops without applying, returns the
+    /// operations that `apply` would apply
+    pub fn dry_run(&self) -> Result<Vec<Operation>> {
+        let spec = self.clone();
+        with_com_objects(move |o| Ok(plan(&spec, &o.snapshot()?)))
+    }
+}
+
+/// Operations that turn the desktops of the snapshot into the spec
+///
+/// Desktops of the spec are matched to the desktops of the snapshot by the
+/// GUID first, and then by the name. Matched desktops are renamed, given the
+/// wallpaper and moved to the order of the spec with the fewest moves, the
+/// spec desktops without a match are created in place, and the unmatched
+/// desktops are removed if `remove_extra` is set.
+pub fn plan(spec: &DesktopSpec, snapshot: &DesktopSnapshot) -> Vec<Operation> {
+    let matches = match_desktops(spec, snapshot);
+    let mut operations = Vec::new();
+
+    for (wanted, matched) in spec.desktops.iter().zip(matches.iter()) {
+        let Some(existing) = matched.map(|i| &snapshot.desktops[i]) else {
+            continue;
+        };
+        if existing.name != wanted.name {
+            operations.push(Operation::Rename {
+                desktop: existing.id,
+                name: wanted.name.clone(),
+            });
+        }
+        if let Some(wallpaper) = &wanted.wallpaper {
+            if existing.wallpaper != *wallpaper {
+                operations.push(Operation::SetWallpaper {
+                    desktop: existing.id,
+                    wallpaper: wallpaper.clone(),
+                });
+            }
+        }
+    }
+
+    // Matched desktops in the spec order, and the unmatched after them, so
+    // that creating at the spec index puts the new desktops in place
+    let current: Vec<GUID> = snapshot.desktops.iter().map(|d| d.id).collect();
+    let unmatched: Vec<GUID> = (0..current.len())
+        .filter(|i| !matches.contains(&Some(*i)))
+        .map(|i| current[i])
+        .collect();
+    let target: Vec<GUID> = matches
+        .iter()
+        .flatten()
+        .map(|i| current[*i])
+        .chain(unmatched.iter().copied())
+        .collect();
+    for (desktop, index) in plan_moves(&current, &target) {
+        operations.push(Operation::Move { desktop, index });
+    }
+
+    for (index, (wanted, matched)) in spec.desktops.iter().zip(matches.iter()).enumerate() {
+        if matched.is_none() {
+            operations.push(Operation::Create {
+                index: index as u32,
+                name: wanted.name.clone(),
+                wallpaper: wanted.wallpaper.clone(),
+            });
+        }
+    }
+
+    if spec.remove_extra {
+        // The first spec desktop is at index 0 after the moves and creations,
+        // the GUID is used when it's known
+        let (fallback, removed) = match matches.first() {
+            Some(Some(i)) => (Desktop::from(current[*i]), &unmatched[..]),
+            Some(None) => (Desktop::from(0), &unmatched[..]),
+            None => match unmatched.split_first() {
+                Some((kept, rest)) => (Desktop::from(*kept), rest),
+                None => (Desktop::from(0), &unmatched[..]),
+            },
+        };
+        for desktop in removed {
+            operations.push(Operation::Remove {
+                desktop: *desktop,
+                fallback,
+            });
+        }
+    }
+    operations
+}
+
+/// Index of the snapshot desktop matched to each spec desktop
+fn match_desktops(spec: &DesktopSpec, snapshot: &DesktopSnapshot) -> Vec<Option<usize>> {
+    let mut matches: Vec<Option<usize>> = spec
+        .desktops
+        .iter()
+        .map(|wanted| wanted.id.and_then(|id| snapshot.index_of(&id)))
+        .collect();
+    for (i, wanted) in spec.desktops.iter().enumerate() {
+        if matches[i].is_some() {
+            continue;
+        }
+        matches[i] = snapshot
+            .desktops
+            .iter()
+            .enumerate()
+            .position(|(j, d)| d.name == wanted.name && !matches.contains(&Some(j)));
+    }
+    matches
+}
+
+/// Apply the operations of a plan in order, stops at the first error
+pub fn apply_plan(operations: &[Operation]) -> Result<()> {
+    let operations = operations.to_vec();
+    with_com_objects(move |o| apply_plan_with(o, &operations))
+}
+
+pub(crate) fn apply_plan_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    operations: &[Operation],
+) -> Result<()> {
+    for operation in operations {
+        match operation {
+            Operation::Rename { desktop, name } => backend.set_desktop_name(*desktop, name)?,
+            Operation::SetWallpaper { desktop, wallpaper } => {
+                backend.set_desktop_wallpaper(*desktop, wallpaper)?
+            }
+            Operation::Move { desktop, index } => backend.move_desktop(*desktop, *index)?,
+            Operation::Create {
+                index,
+                name,
+                wallpaper,
+            } => {
+                let mut builder = create_desktop_with().name(name).at_index(*index);
+                if let Some(wallpaper) = wallpaper {
+                    builder = builder.wallpaper(wallpaper);
+                }
+                builder.build_with(backend)?;
+            }
+            Operation::Remove { desktop, fallback } => {
+                remove_desktop_with(
+                    backend,
+                    &Desktop::from(*desktop),
+                    FallbackStrategy::Specific(*fallback),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+    use crate::DesktopState;
+
+    fn snapshot(desktops: &[(u32, &str, &str)]) -> DesktopSnapshot {
+        DesktopSnapshot {
+            desktops: desktops
+                .iter()
+                .map(|(n, name, wallpaper)| DesktopState {
+                    id: guid(*n),
+                    name: name.to_string(),
+                    wallpaper: wallpaper.to_string(),
+                })
+                .collect(),
+            current: Some(guid(1)),
+        }
+    }
+
+    fn spec(names: &[&str]) -> DesktopSpec {
+        DesktopSpec {
+            desktops: names.iter().map(|n| SpecDesktop::named(n)).collect(),
+            remove_extra: false,
+        }
+    }
+
+    #[test]
+    fn test_plan_nothing_to_do() {
+        let snapshot = snapshot(&[(1, "Mail", "a.jpg"), (2, "Code", "b.jpg")]);
+        let mut spec = spec(&["Mail", "Code"]);
+        spec.desktops[1].wallpaper = Some("b.jpg".to_owned());
+        assert_eq!(plan(&spec, &snapshot), vec![]);
+    }
+
+    #[test]
+    fn test_plan_by_name() {
+        let snapshot = snapshot(&[(1, "Chat", ""), (2, "Mail", ""), (3, "Other", "")]);
+        let mut spec = spec(&["Mail", "Code", "Chat"]);
+        spec.remove_extra = true;
+        assert_eq!(
+            plan(&spec, &snapshot),
+            vec![
+                Operation::Move {
+                    desktop: guid(1),
+                    index: 1
+                },
+                Operation::Create {
+                    index: 1,
+                    name: "Code".to_owned(),
+                    wallpaper: None
+                },
+                Operation::Remove {
+                    desktop: guid(3),
+                    fallback: Desktop::from(guid(2))
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_remove_first_desktop() {
+        // Nothing matches, the created desktop is the fallback
+        let backend = SimulatedBackend::new(2).with_window(10, 1, false);
+        let mut spec = spec(&["Mail"]);
+        spec.remove_extra = true;
+        let operations = plan(&spec, &backend.snapshot().unwrap());
+        assert_eq!(
+            operations[1..],
+            [
+                Operation::Remove {
+                    desktop: guid(1),
+                    fallback: Desktop::from(0)
+                },
+                Operation::Remove {
+                    desktop: guid(2),
+                    fallback: Desktop::from(0)
+                },
+            ]
+        );
+        apply_plan_with(&backend, &operations).unwrap();
+        assert_eq!(backend.names(), vec!["Mail"]);
+        assert_eq!(backend.windows_on(3), vec![10]);
+
+        // Empty spec keeps the first desktop
+        let backend = SimulatedBackend::new(3).with_window(20, 2, false);
+        let spec = DesktopSpec {
+            desktops: vec![],
+            remove_extra: true,
+        };
+        let operations = plan(&spec, &backend.snapshot().unwrap());
+        assert_eq!(
+            operations,
+            vec![
+                Operation::Remove {
+                    desktop: guid(2),
+                    fallback: Desktop::from(guid(1))
+                },
+                Operation::Remove {
+                    desktop: guid(3),
+                    fallback: Desktop::from(guid(1))
+                },
+            ]
+        );
+        apply_plan_with(&backend, &operations).unwrap();
+        assert_eq!(backend.names(), vec!["D1"]);
+        assert_eq!(backend.windows_on(1), vec![20]);
+    }
+
+    #[test]
+    fn test_plan_by_guid_first() {
+        let snapshot = snapshot(&[(1, "Code", "a.jpg"), (2, "Mail", "b.jpg")]);
+        let spec = DesktopSpec {
+            desktops: vec![SpecDesktop::named("Code")
+                .with_id(guid(2))
+                .with_wallpaper("c.jpg")],
+            remove_extra: false,
+        };
+
+        // The desktop named "Code" does not match, as the GUID matches first
+        assert_eq!(
+            plan(&spec, &snapshot),
+            vec![
+                Operation::Rename {
+                    desktop: guid(2),
+                    name: "Code".to_owned()
+                },
+                Operation::SetWallpaper {
+                    desktop: guid(2),
+                    wallpaper: "c.jpg".to_owned()
+                },
+                Operation::Move {
+                    desktop: guid(1),
+                    index: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_duplicate_names() {
+        let snapshot = snapshot(&[(1, "Work", ""), (2, "Work", "")]);
+        let spec = spec(&["Work", "Work", "Work"]);
+        assert_eq!(
+            plan(&spec, &snapshot),
+            vec![Operation::Create {
+                index: 2,
+                name: "Work".to_owned(),
+                wallpaper: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_operation_display() {
+        let operation = Operation::Create {
+            index: 2,
+            name: "Build".to_owned(),
+            wallpaper: Some("C:\\build.jpg".to_owned()),
+        };
+        assert_eq!(
+            operation.to_string(),
+            "create desktop \"Build\" at 2 with wallpaper C:\\build.jpg"
+        );
+        assert_eq!(
+            Operation::Move {
+                desktop: guid(3),
+                index: 0
+            }
+            .to_string(),
+            "move desktop 00000003-0000-0000-0000-000000000000 to 0"
+        );
+    }
+
+    #[test]
+    fn test_apply_reaches_spec() {
+        let backend = SimulatedBackend::new(4).with_window(30, 3, false);
+        backend.set_desktop_name(guid(4), "Mail").unwrap();
+        backend.set_desktop_name(guid(2), "Chat").unwrap();
+        let spec = DesktopSpec {
+            desktops: vec![
+                SpecDesktop::named("Mail"),
+                SpecDesktop::named("Code").with_wallpaper("C:\\code.jpg"),
+                SpecDesktop::named("Build"),
+                SpecDesktop::named("Chat"),
+            ],
+            remove_extra: true,
+        };
+        let operations = plan(&spec, &backend.snapshot().unwrap());
+        apply_plan_with(&backend, &operations).unwrap();
+        assert_eq!(backend.names(), vec!["Mail", "Code", "Build", "Chat"]);
+        assert_eq!(backend.desktop_wallpaper(guid(5)).unwrap(), "C:\\code.jpg");
+        assert_eq!(backend.windows_on(4), vec![30]);
+
+        // Applying again does nothing
+        assert_eq!(plan(&spec, &backend.snapshot().unwrap()), vec![]);
+    }
+}
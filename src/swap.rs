@@ -0,0 +1,330 @@
+/// Swapping the positions, or the windows, names and wallpapers of two
+/// desktops
+use crate::backend::DesktopBackend;
+use crate::comobjects::with_com_objects;
+use crate::{Desktop, Error, Result, Window};
+use windows::core::GUID;
+
+/// Step of a multi-step operation that failed, the other steps are still
+/// tried when possible
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedStep {
+    /// Description of the step, e.g. "move window 0x1A2B to desktop 2"
+    pub step: String,
+    pub error: Error,
+}
+
+impl FailedStep {
+    pub(crate) fn new(step: String, error: Error) -> FailedStep {
+        FailedStep { step, error }
+    }
+}
+
+/// Result of `swap_desktops`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapReport {
+    pub a: Desktop,
+    pub b: Desktop,
+    pub failures: Vec<FailedStep>,
+}
+
+impl SwapReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Result of `swap_desktop_contents`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwapContentsReport {
+    pub a: Desktop,
+    pub b: Desktop,
+    pub moved_to_a: Vec<Window>,
+    pub moved_to_b: Vec<Window>,
+
+    /// Pinned windows are on all desktops, and are not moved
+    pub skipped_pinned: Vec<Window>,
+    pub names_swapped: bool,
+    pub wallpapers_swapped: bool,
+    pub failures: Vec<FailedStep>,
+}
+
+impl SwapContentsReport {
+    /// Were all steps successful?
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Exchange the positions of two desktops by index or GUID
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// let report = swap_desktops(0, 2).unwrap();
+/// for failure in report.failures {
+///     println!("{} failed: {}", failure.step, failure.error);
+/// }
+/// ```
+pub fn swap_desktops<T>(a: T, b: T) -> Result<SwapReport>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| swap_desktops_with(o, &a.into(), &b.into()))
+}
+
+/// Exchange the windows, names and wallpapers of two desktops by index or
+/// GUID, the desktops stay in place
+pub fn swap_desktop_contents<T>(a: T, b: T) -> Result<SwapContentsReport>
+where
+    T: Into<Desktop>,
+    T: Send + 'static + Copy,
+{
+    with_com_objects(move |o| swap_desktop_contents_with(o, &a.into(), &b.into()))
+}
+
+pub(crate) fn swap_desktops_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    a: &Desktop,
+    b: &Desktop,
+) -> Result<SwapReport> {
+    let a = backend.resolve(a)?;
+    let b = backend.resolve(b)?;
+    let mut report = SwapReport {
+        a: a.into(),
+        b: b.into(),
+        failures: Vec::new(),
+    };
+    if a == b {
+        return Ok(report);
+    }
+
+    // Move the first one to the place of the last one, which shifts the last
+    // one towards the first one by one, and then move the last one
+    let (ia, ib) = (backend.index_of(a)?, backend.index_of(b)?);
+    let (first, last, first_index, last_index) = if ia < ib {
+        (a, b, ia, ib)
+    } else {
+        (b, a, ib, ia)
+    };
+    if let Err(er) = backend.move_desktop(first, last_index) {
+        report.failures.push(FailedStep::new(
+            format!("move desktop {:?} to {}", first, last_index),
+            er,
+        ));
+        return Ok(report);
+    }
+    if let Err(er) = backend.move_desktop(last, first_index) {
+        report.failures.push(FailedStep::new(
+            format!("move desktop {:?} to {}", last, first_index),
+            er,
+        ));
+    }
+    Ok(report)
+}
+
+pub(crate) fn swap_desktop_contents_with<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    a: &Desktop,
+    b: &Desktop,
+) -> Result<SwapContentsReport> {
+    let a = backend.resolve(a)?;
+    let b = backend.resolve(b)?;
+    let mut report = SwapContentsReport {
+        a: a.into(),
+        b: b.into(),
+        moved_to_a: Vec::new(),
+        moved_to_b: Vec::new(),
+        skipped_pinned: Vec::new(),
+        names_swapped: false,
+        wallpapers_swapped: false,
+        failures: Vec::new(),
+    };
+    if a == b {
+        return Ok(report);
+    }
+
+    // Windows are listed before moving, so that moved windows are not moved
+    // back
+    let windows_a = backend.desktop_windows(a)?;
+    let windows_b = backend.desktop_windows(b)?;
+    report.moved_to_b = move_windows(
+        backend,
+        &windows_a,
+        b,
+        &mut report.skipped_pinned,
+        &mut report.failures,
+    );
+    report.moved_to_a = move_windows(
+        backend,
+        &windows_b,
+        a,
+        &mut report.skipped_pinned,
+        &mut report.failures,
+    );
+
+    report.names_swapped = swap_property(
+        "name",
+        a,
+        b,
+        |id| backend.desktop_name(id),
+        |id, value| backend.set_desktop_name(id, value),
+        &mut report.failures,
+    );
+    report.wallpapers_swapped = swap_property(
+        "wallpaper",
+        a,
+        b,
+        |id| backend.desktop_wallpaper(id),
+        |id, value| backend.set_desktop_wallpaper(id, value),
+        &mut report.failures,
+    );
+    Ok(report)
+}
+
+/// Move the windows that are not pinned, returns the moved windows
+pub(crate) fn move_windows<B: DesktopBackend + ?Sized>(
+    backend: &B,
+    windows: &[windows::Win32::Foundation::HWND],
+    target: GUID,
+    skipped_pinned: &mut Vec<Window>,
+    failures: &mut Vec<FailedStep>,
+) -> Vec<Window> {
+    let mut moved = Vec::new();
+    for hwnd in windows.iter() {
+        match backend.is_window_pinned(*hwnd) {
+            Ok(true) => skipped_pinned.push(Window(*hwnd)),
+            Ok(false) => match backend.move_window(*hwnd, target) {
+                Ok(()) => moved.push(Window(*hwnd)),
+                Err(er) => failures.push(FailedStep::new(
+                    format!("move window 0x{:X} to desktop {:?}", hwnd.0, target),
+                    er,
+                )),
+            },
+            Err(er) => failures.push(FailedStep::new(
+                format!("check if window 0x{:X} is pinned", hwnd.0),
+                er,
+            )),
+        }
+    }
+    moved
+}
+
+/// Exchange a property of two desktops, returns true if both were set
+fn swap_property(
+    property: &str,
+    a: GUID,
+    b: GUID,
+    get: impl Fn(GUID) -> Result<String>,
+    set: impl Fn(GUID, &str) -> Result<()>,
+    failures: &mut Vec<FailedStep>,
+) -> bool {
+    let values = get(a).and_then(|value_a| Ok((value_a, get(b)?)));
+    let (value_a, value_b) = match values {
+        Ok(values) => values,
+        Err(er) => {
+            failures.push(FailedStep::new(format!("get {}", property), er));
+            return false;
+        }
+    };
+    let mut swapped = true;
+    for (id, value) in [(a, &value_b), (b, &value_a)] {
+        if let Err(er) = set(id, value) {
+            failures.push(FailedStep::new(
+                format!("set {} of desktop {:?}", property, id),
+                er,
+            ));
+            swapped = false;
+        }
+    }
+    swapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::simulated::{guid, SimulatedBackend};
+    use windows::Win32::Foundation::HWND;
+
+    #[test]
+    fn test_swap_desktops() {
+        let backend = SimulatedBackend::new(4);
+        let report = swap_desktops_with(&backend, &Desktop::from(3), &Desktop::from(0)).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.a, Desktop::from(guid(4)));
+        assert_eq!(backend.names(), vec!["D4", "D2", "D3", "D1"]);
+
+        let report = swap_desktops_with(&backend, &Desktop::from(1), &Desktop::from(2)).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(backend.names(), vec!["D4", "D3", "D2", "D1"]);
+    }
+
+    #[test]
+    fn test_swap_same_desktop() {
+        let backend = SimulatedBackend::new(2);
+        let report =
+            swap_desktops_with(&backend, &Desktop::from(1), &Desktop::from(guid(2))).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(backend.names(), vec!["D1", "D2"]);
+    }
+
+    #[test]
+    fn test_swap_desktops_failure() {
+        let backend = SimulatedBackend::new(3);
+        backend.fail("move_desktop");
+        let report = swap_desktops_with(&backend, &Desktop::from(0), &Desktop::from(2)).unwrap();
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].error, Error::ServerBusy);
+        assert_eq!(backend.names(), vec!["D1", "D2", "D3"]);
+
+        assert_eq!(
+            swap_desktops_with(&backend, &Desktop::from(0), &Desktop::from(5)),
+            Err(Error::DesktopNotFound)
+        );
+    }
+
+    #[test]
+    fn test_swap_contents() {
+        let backend = SimulatedBackend::new(3)
+            .with_window(10, 1, false)
+            .with_window(11, 1, true)
+            .with_window(20, 2, false)
+            .with_window(21, 2, false);
+        let report =
+            swap_desktop_contents_with(&backend, &Desktop::from(0), &Desktop::from(1)).unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.moved_to_b, vec![Window(HWND(10))]);
+        assert_eq!(report.moved_to_a, vec![Window(HWND(20)), Window(HWND(21))]);
+        assert_eq!(report.skipped_pinned, vec![Window(HWND(11))]);
+        assert!(report.names_swapped && report.wallpapers_swapped);
+        assert_eq!(backend.windows_on(1), vec![11, 20, 21]);
+        assert_eq!(backend.windows_on(2), vec![10]);
+        assert_eq!(backend.names(), vec!["D2", "D1", "D3"]);
+        assert_eq!(backend.desktop_wallpaper(guid(1)).unwrap(), "C:\\wall2.jpg");
+    }
+
+    #[test]
+    fn test_swap_contents_partial_failure() {
+        let backend = SimulatedBackend::new(2)
+            .with_window(10, 1, false)
+            .with_window(11, 1, false)
+            .with_window(20, 2, false);
+        backend.fail("move_window:11");
+        backend.fail("set_desktop_wallpaper");
+        let report =
+            swap_desktop_contents_with(&backend, &Desktop::from(0), &Desktop::from(1)).unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(report.moved_to_b, vec![Window(HWND(10))]);
+        assert_eq!(report.moved_to_a, vec![Window(HWND(20))]);
+        assert!(report.names_swapped);
+        assert!(!report.wallpapers_swapped);
+        assert_eq!(report.failures.len(), 3);
+        assert_eq!(
+            report.failures[0].step,
+            "move window 0xB to desktop 00000002-0000-0000-0000-000000000000"
+        );
+    }
+}
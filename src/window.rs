@@ -0,0 +1,268 @@
+/// Windows and their desktops
+use crate::comobjects::with_com_objects;
+use crate::{move_window_and_follow, App, Desktop, Error, Result};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use windows::core::{GUID, PWSTR};
+use windows::Win32::Foundation::{CloseHandle, HWND};
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetClassNameW, GetWindowTextW, GetWindowThreadProcessId, IsIconic, SetForegroundWindow,
+    ShowWindow, SW_RESTORE,
+};
+
+/// Top level window
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// # let hwnd = windows::Win32::Foundation::HWND(0);
+/// let window = Window::from(hwnd);
+/// if !window.is_on_current_desktop().unwrap() {
+///     window.move_to(get_current_desktop().unwrap()).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window(pub HWND);
+
+impl Hash for Window {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0 .0.hash(state);
+    }
+}
+
+impl From<HWND> for Window {
+    fn from(hwnd: HWND) -> Self {
+        Window(hwnd)
+    }
+}
+
+impl From<Window> for HWND {
+    fn from(window: Window) -> Self {
+        window.0
+    }
+}
+
+/// Information of the window, fields that could not be read are empty
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowInfo {
+    pub window: Window,
+    pub title: String,
+    pub class_name: String,
+
+    /// Application user model ID
+    pub app_id: Option<String>,
+
+    /// Path of the executable
+    pub exe: Option<String>,
+
+    pub desktop: Option<Desktop>,
+    pub pinned: bool,
+}
+
+impl Window {
+    /// Window handle
+    pub fn hwnd(&self) -> HWND {
+        self.0
+    }
+
+    /// Desktop of the window
+    pub fn desktop(&self) -> Result<Desktop> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.get_desktop_by_window(&hwnd).map(Desktop::from))
+    }
+
+    /// Move the window to desktop by index or GUID
+    pub fn move_to<T>(&self, desktop: T) -> Result<()>
+    where
+        T: Into<Desktop>,
+        T: Send + 'static + Copy,
+    {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.move_window_to_desktop(&hwnd, &desktop.into().into()))
+    }
+
+    /// Move the window to desktop by index or GUID, switch to that desktop and
+    /// bring the window to the foreground
+    pub fn move_and_follow<T>(&self, desktop: T) -> Result<()>
+    where
+        T: Into<Desktop>,
+        T: Send + 'static + Copy,
+    {
+        move_window_and_follow(self.0, desktop)
+    }
+
+    /// Move the window to the current desktop, see `summon_window`
+    pub fn summon(&self, restore: bool) -> Result<()> {
+        summon_window(self.0, restore)
+    }
+
+    /// Send the window back to the desktop it was summoned from, see
+    /// `unsummon_window`
+    pub fn unsummon(&self) -> Result<Option<Desktop>> {
+        unsummon_window(self.0)
+    }
+
+    /// Is the window on desktop by index or GUID
+    pub fn is_on_desktop<T>(&self, desktop: T) -> Result<bool>
+    where
+        T: Into<Desktop>,
+        T: Send + 'static + Copy,
+    {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.is_window_on_desktop(&hwnd, &desktop.into().into()))
+    }
+
+    pub fn is_on_current_desktop(&self) -> Result<bool> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.is_window_on_current_desktop(&hwnd))
+    }
+
+    /// Is window pinned to all desktops?
+    pub fn is_pinned(&self) -> Result<bool> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.is_pinned_window(&hwnd))
+    }
+
+    /// Pin window to all desktops
+    pub fn pin(&self) -> Result<()> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.pin_window(&hwnd))
+    }
+
+    /// Unpin window
+    pub fn unpin(&self) -> Result<()> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.unpin_window(&hwnd))
+    }
+
+    /// Application of the window
+    pub fn app(&self) -> Result<App> {
+        let hwnd = self.0;
+        with_com_objects(move |o| o.get_app_id(&hwnd)).map(|id| App::new(&id))
+    }
+
+    /// Window title
+    pub fn title(&self) -> String {
+        let mut text = [0u16; 512];
+        let len = unsafe { GetWindowTextW(self.0, &mut text) };
+        String::from_utf16_lossy(&text[..len.max(0) as usize])
+    }
+
+    /// Window class name
+    pub fn class_name(&self) -> String {
+        let mut text = [0u16; 256];
+        let len = unsafe { GetClassNameW(self.0, &mut text) };
+        String::from_utf16_lossy(&text[..len.max(0) as usize])
+    }
+
+    /// Path of the executable that owns the window
+    pub fn exe(&self) -> Option<String> {
+        let mut pid = 0;
+        unsafe { GetWindowThreadProcessId(self.0, Some(&mut pid)) };
+        if pid == 0 {
+            return None;
+        }
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+        let mut path = [0u16; 1024];
+        let mut len = path.len() as u32;
+        let res = unsafe {
+            QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                PWSTR(path.as_mut_ptr()),
+                &mut len,
+            )
+        };
+        let _ = unsafe { CloseHandle(process) };
+        res.ok()?;
+        Some(String::from_utf16_lossy(&path[..len as usize]))
+    }
+
+    /// Get the title, class, application, executable, desktop and pinned
+    /// state of the window
+    pub fn info(&self) -> Result<WindowInfo> {
+        let hwnd = self.0;
+        let (app_id, desktop, pinned) = with_com_objects(move |o| {
+            Ok((
+                o.get_app_id(&hwnd).ok(),
+                o.get_desktop_by_window(&hwnd).ok().map(Desktop::from),
+                o.is_pinned_window(&hwnd).unwrap_or(false),
+            ))
+        })?;
+        Ok(WindowInfo {
+            window: *self,
+            title: self.title(),
+            class_name: self.class_name(),
+            app_id,
+            exe: self.exe(),
+            desktop,
+            pinned,
+        })
+    }
+}
+
+/// Bring the window to the foreground
+pub(crate) fn focus_window(hwnd: HWND) {
+    if !unsafe { SetForegroundWindow(hwnd) }.as_bool() {
+        ::log::debug!(target: crate::log::COM, "SetForegroundWindow failed for {:?}", hwnd);
+    }
+}
+
+/// Original desktops of the summoned windows
+static SUMMONED: Mutex<BTreeMap<isize, GUID>> = Mutex::new(BTreeMap::new());
+
+/// Move the window from its desktop to the current desktop and bring it to
+/// the foreground, minimized window is restored if `restore` is true
+///
+/// The original desktop is remembered, and `unsummon_window` sends the window
+/// back there.
+pub fn summon_window(hwnd: HWND, restore: bool) -> Result<()> {
+    let origin = with_com_objects(move |o| {
+        let origin = if o.is_window_on_current_desktop(&hwnd)? {
+            None
+        } else {
+            let origin = o.get_desktop_id(&o.get_desktop_by_window(&hwnd)?)?;
+            o.move_window_to_desktop(&hwnd, &o.get_current_desktop()?)?;
+            Some(origin)
+        };
+        if restore && unsafe { IsIconic(hwnd) }.as_bool() {
+            let _ = unsafe { ShowWindow(hwnd, SW_RESTORE) };
+        }
+        focus_window(hwnd);
+        Ok(origin)
+    })?;
+
+    // Summoning again keeps the first original desktop
+    if let (Some(origin), Ok(mut summoned)) = (origin, SUMMONED.lock()) {
+        summoned.entry(hwnd.0).or_insert(origin);
+    }
+    Ok(())
+}
+
+/// Send the summoned window back to its original desktop, returns the
+/// desktop, or `None` if the window was not summoned
+pub fn unsummon_window(hwnd: HWND) -> Result<Option<Desktop>> {
+    let origin = SUMMONED
+        .lock()
+        .map_err(|_| Error::InternalBorrowError)?
+        .get(&hwnd.0)
+        .copied();
+    let Some(origin) = origin else {
+        return Ok(None);
+    };
+    let result =
+        with_com_objects(move |o| o.move_window_to_desktop(&hwnd, &Desktop::from(origin).into()));
+
+    // Forget the window once it's moved, or if the window or the desktop is gone
+    if result.as_ref().map_or_else(Error::is_not_found, |_| true) {
+        if let Ok(mut summoned) = SUMMONED.lock() {
+            summoned.remove(&hwnd.0);
+        }
+    }
+    result.map(|_| Some(Desktop::from(origin)))
+}
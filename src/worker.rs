@@ -0,0 +1,197 @@
+/// Dedicated COM worker thread
+///
+/// By default the COM objects are created separately for each thread that
+/// calls the API. In `ComThreadMode::WorkerThread` mode all calls are sent to
+/// a single long-lived thread, and the caller waits for the result.
+use crate::{Error, Result};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Where the COM calls are executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ComThreadMode {
+    /// COM objects are created for each calling thread, this is the default
+    #[default]
+    CallerThread,
+
+    /// All COM calls are executed in a single worker thread
+    WorkerThread,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static WORKER_ENABLED: AtomicBool = AtomicBool::new(false);
+static WORKER_SENDER: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+static DEFAULT_TIMEOUT: Mutex<Option<Duration>> = Mutex::new(None);
+
+thread_local! {
+    static IS_WORKER_THREAD: Cell<bool> = const { Cell::new(false) };
+    static CALL_TIMEOUT: Cell<Option<Duration>> = const { Cell::new(None) };
+}
+
+/// Set where the COM calls are executed
+///
+/// Switching back to `ComThreadMode::CallerThread` lets the worker thread
+/// finish the calls already sent to it, and then the thread exits.
+pub fn set_com_thread_mode(mode: ComThreadMode) {
+    WORKER_ENABLED.store(mode == ComThreadMode::WorkerThread, Ordering::SeqCst);
+    if mode == ComThreadMode::CallerThread {
+        if let Ok(mut sender) = WORKER_SENDER.lock() {
+            sender.take();
+        }
+    }
+}
+
+/// Get where the COM calls are executed
+pub fn get_com_thread_mode() -> ComThreadMode {
+    if WORKER_ENABLED.load(Ordering::SeqCst) {
+        ComThreadMode::WorkerThread
+    } else {
+        ComThreadMode::CallerThread
+    }
+}
+
+/// Set the timeout for calls executed in the worker thread, `None` waits
+/// forever, which is the default
+pub fn set_default_call_timeout(timeout: Option<Duration>) {
+    if let Ok(mut default_timeout) = DEFAULT_TIMEOUT.lock() {
+        *default_timeout = timeout;
+    }
+}
+
+/// Run `f` with a different timeout for the calls made from it, this has an
+/// effect only in `ComThreadMode::WorkerThread` mode
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use winvd::*;
+/// set_com_thread_mode(ComThreadMode::WorkerThread);
+/// let result = with_call_timeout(std::time::Duration::from_millis(500), || switch_desktop(1));
+/// if result == Err(Error::CallTimeout) {
+///     println!("Explorer is not responding");
+/// }
+/// ```
+pub fn with_call_timeout<R>(timeout: Duration, f: impl FnOnce() -> R) -> R {
+    // Restores the previous timeout also if `f` panics
+    struct Restore(Option<Duration>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CALL_TIMEOUT.with(|t| t.set(self.0));
+        }
+    }
+
+    let _restore = Restore(CALL_TIMEOUT.with(|t| t.replace(Some(timeout))));
+    f()
+}
+
+/// Should the call be sent to the worker thread?
+pub(crate) fn should_use_worker() -> bool {
+    WORKER_ENABLED.load(Ordering::SeqCst) && !IS_WORKER_THREAD.with(|w| w.get())
+}
+
+/// Run the function in the worker thread and wait for the result
+pub(crate) fn call_in_worker<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let timeout = CALL_TIMEOUT
+        .with(|t| t.get())
+        .or_else(|| DEFAULT_TIMEOUT.lock().ok().and_then(|t| *t));
+    let (tx, rx) = channel::<Result<T>>();
+    let job: Job = Box::new(move || {
+        // Caller may have given up waiting, in which case the result is dropped
+        let _ = tx.send(f());
+    });
+    send_job(job)?;
+
+    match timeout {
+        Some(timeout) => rx.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => Error::CallTimeout,
+            RecvTimeoutError::Disconnected => Error::ComWorkerFailed,
+        })?,
+        None => rx.recv().map_err(|_| Error::ComWorkerFailed)?,
+    }
+}
+
+fn send_job(job: Job) -> Result<()> {
+    let mut sender = WORKER_SENDER
+        .lock()
+        .map_err(|_| Error::InternalBorrowError)?;
+    let job = match sender.as_ref() {
+        Some(s) => match s.send(job) {
+            Ok(()) => return Ok(()),
+            // Worker thread has exited, start a new one
+            Err(err) => err.0,
+        },
+        None => job,
+    };
+    let new_sender = spawn_worker()?;
+    new_sender.send(job).map_err(|_| Error::ComWorkerFailed)?;
+    *sender = Some(new_sender);
+    Ok(())
+}
+
+fn spawn_worker() -> Result<Sender<Job>> {
+    let (tx, rx) = channel::<Job>();
+    std::thread::Builder::new()
+        .name("winvd-com-worker".to_owned())
+        .spawn(move || {
+            IS_WORKER_THREAD.with(|w| w.set(true));
+            for job in rx {
+                // Panic in a job must not take the worker down, the caller
+                // gets `ComWorkerFailed` as the result sender is dropped
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+            }
+        })
+        .map_err(|_| Error::ComWorkerFailed)?;
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_run_in_single_worker_thread() {
+        let first = call_in_worker(|| Ok(std::thread::current().id())).unwrap();
+        let second = call_in_worker(|| Ok(std::thread::current().id())).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, std::thread::current().id());
+        assert!(call_in_worker(|| Ok(!should_use_worker())).unwrap());
+    }
+
+    #[test]
+    fn test_call_timeout() {
+        let result = with_call_timeout(Duration::from_millis(10), || {
+            call_in_worker(|| {
+                std::thread::sleep(Duration::from_millis(200));
+                Ok(())
+            })
+        });
+        assert_eq!(result, Err(Error::CallTimeout));
+
+        // Worker is still usable after the timeout
+        assert_eq!(call_in_worker(|| Ok(1)), Ok(1));
+    }
+
+    #[test]
+    fn test_call_timeout_restored_after_panic() {
+        let result = std::panic::catch_unwind(|| {
+            with_call_timeout(Duration::from_millis(10), || panic!("Failing closure"))
+        });
+        assert!(result.is_err());
+        assert_eq!(CALL_TIMEOUT.with(|t| t.get()), None);
+    }
+
+    #[test]
+    fn test_panic_in_worker() {
+        let result: Result<()> = call_in_worker(|| panic!("Failing call"));
+        assert_eq!(result, Err(Error::ComWorkerFailed));
+        assert_eq!(call_in_worker(|| Ok(1)), Ok(1));
+    }
+}